@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::limits::Guard;
+use crate::source::{infer_compressed_format, read_header, CompressedFormat};
+
+// Number of bytes of the first header block of TAR, where its magic is at the offset 257.
+const TAR_BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+// Detect whether the file is a ZIP archive, or a TAR archive which may be compressed, such as `.tar.gz`.
+// Returns the archive format and the compression format of the whole archive.
+pub fn detect_archive(path: &Path) -> Result<Option<(ArchiveFormat, CompressedFormat)>> {
+    let header = read_header(&mut File::open(path)?)?;
+    Ok(detect_archive_in_header(&header))
+}
+
+// Detect an archive from the first bytes of the input, for the inputs which are not read from a file yet.
+pub fn detect_archive_in_header(header: &[u8]) -> Option<(ArchiveFormat, CompressedFormat)> {
+    if infer::archive::is_zip(header) {
+        debug!("Provided input is a ZIP archive");
+        return Some((ArchiveFormat::Zip, CompressedFormat::None));
+    }
+
+    let compressed_format = infer_compressed_format(header);
+    let is_tar = match compressed_format {
+        CompressedFormat::None => infer::archive::is_tar(header),
+        // BAM and BCF in BGZF are parsed without being decompressed.
+        CompressedFormat::Bgzf => false,
+        _ => {
+            // look into the first block of the decompressed content, which the first compressed bytes are enough for.
+            let mut decompressed_header = Vec::with_capacity(TAR_BLOCK_SIZE);
+            compressed_format.decoder(header).is_ok_and(|decoder| {
+                decoder
+                    .take(TAR_BLOCK_SIZE as u64)
+                    .read_to_end(&mut decompressed_header)
+                    .is_ok()
+            }) && infer::archive::is_tar(&decompressed_header)
+        }
+    };
+
+    if is_tar {
+        debug!("Provided input is a TAR archive");
+        Some((ArchiveFormat::Tar, compressed_format))
+    } else {
+        None
+    }
+}
+
+// Call `f` with the path and the content of each regular file in the archive, in the order they are stored.
+// The decompressed content of the whole archive is checked against the limits of `guard`.
+pub fn for_each_member<F>(
+    path: &Path,
+    archive_format: ArchiveFormat,
+    compressed_format: &CompressedFormat,
+    guard: &Guard,
+    mut f: F,
+) -> Result<()>
+where
+    F: FnMut(&str, &mut dyn Read) -> Result<()>,
+{
+    match archive_format {
+        ArchiveFormat::Zip => {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)
+                .context("Failed to read the ZIP archive")?;
+            for index in 0..archive.len() {
+                let mut member = archive
+                    .by_index(index)
+                    .context("Failed to read the ZIP archive")?;
+                if !member.is_file() {
+                    continue;
+                }
+                let name = member
+                    .name()
+                    .context("Failed to read the path of a file in the ZIP archive")?
+                    .into_owned();
+                guard.add_compressed(member.compressed_size());
+                f(&name, &mut guard.check_decompressed(&mut member))?;
+            }
+        }
+        ArchiveFormat::Tar => {
+            let decoder = compressed_format.decoder(guard.count_compressed(File::open(path)?))?;
+            let mut archive = tar::Archive::new(guard.check_decompressed(decoder));
+            for entry in archive
+                .entries()
+                .context("Failed to read the TAR archive")?
+            {
+                let mut entry = entry.context("Failed to read the TAR archive")?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().into_owned();
+                f(&name, &mut entry)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,5 @@
-use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Debug, ValueEnum, Clone, Copy)]
@@ -8,6 +8,8 @@ pub enum OutputFormat {
     Tsv,
     Csv,
     Json,
+    JsonArray,
+    Ndjson,
 }
 
 #[derive(Parser, Debug)]
@@ -17,65 +19,219 @@ pub enum OutputFormat {
     version = env!("CARGO_PKG_VERSION"),
     after_help = concat!("Version: v", env!("CARGO_PKG_VERSION")),
     arg_required_else_help = true,
+    subcommand_negates_reqs = true,
+    override_usage = "tataki [OPTIONS] [FILE|URL|'-']...\n       tataki [OPTIONS] validate [FILE|URL|'-']...\n       tataki --cache-dir <DIR> cache <list|prune|clear>",
 )]
 pub struct Args {
-    /// Path to the file, URL, or "-" to read from standard input. Multiple inputs can be specified.
-    #[clap(name = "FILE|URL|'-'", required_unless_present = "dry_run")]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the file, directory, glob pattern, URL, or "-" to read from standard input. Multiple inputs can be specified.
+    #[clap(name = "FILE|URL|'-'", required_unless_present_any = ["dry_run", "from_file"])]
     pub input: Vec<String>,
 
     /// Path to the output file [default: stdout]
-    #[clap(short, long, value_name = "FILE")]
+    #[clap(short, long, value_name = "FILE", global = true)]
     pub output: Option<PathBuf>,
 
-    #[clap(short = 'f', value_enum, default_value = "csv",conflicts_with_all = ["yaml", "json", "tsv"])]
+    #[clap(short = 'f', value_enum, default_value = "csv",conflicts_with_all = ["yaml", "json", "tsv"], global = true)]
     output_format: OutputFormat,
 
-    #[clap(long, hide = true, conflicts_with_all = ["output_format", "json", "tsv"])]
+    #[clap(long, hide = true, conflicts_with_all = ["output_format", "json", "tsv"], global = true)]
     yaml: bool,
 
-    #[clap(long, hide = true, conflicts_with_all = ["output_format", "yaml", "tsv"])]
+    #[clap(long, hide = true, conflicts_with_all = ["output_format", "yaml", "tsv"], global = true)]
     json: bool,
 
-    #[clap(long, hide = true, conflicts_with_all = ["output_format", "yaml", "json"])]
+    #[clap(long, hide = true, conflicts_with_all = ["output_format", "yaml", "json"], global = true)]
     tsv: bool,
 
+    /// Add the version and the details of the detected formats, such as the sort order of SAM and the range of the read lengths of FASTQ, as the columns of CSV and TSV. They are always output in YAML and JSON.
+    #[clap(long, global = true)]
+    pub details: bool,
+
     /// Specify the directory in which to create a temporary directory. If this option is not provided, a temporary directory will be created in the default system temporary directory (/tmp).
-    #[clap(short = 'C', long, value_name = "DIR")]
+    /// The downloaded files and the detected formats are also cached in `<DIR>/cache` and reused by later runs, unless `--no-cache` is given.
+    #[clap(short = 'C', long, value_name = "DIR", global = true)]
     pub cache_dir: Option<PathBuf>,
 
+    /// Neither reuse nor add to the downloads and the detected formats cached in the directory given by `--cache-dir`.
+    #[clap(long, global = true)]
+    pub no_cache: bool,
+
     // #[clap(long, hide = true)]
     // pub full_fetch: bool,
     /// Specify the tataki configuration file. If this option is not provided, the default configuration will be used.
     /// The option `--dry-run` shows the default configuration file.
-    #[clap(short, long, value_name = "FILE")]
+    #[clap(short, long, value_name = "FILE", global = true)]
     pub conf: Option<PathBuf>,
 
     /// Attempt to read the whole lines from the input files.
-    #[clap(short, long, conflicts_with_all = ["num_records"])]
+    #[clap(short, long, conflicts_with_all = ["num_records"], global = true)]
     pub tidy: bool,
 
     /// Do not try to decompress the input file when detecting the file format.
-    #[clap(long)]
+    #[clap(long, global = true)]
     pub no_decompress: bool,
 
+    /// Maximum number of compression layers to decompress, such as 2 for a gzipped bzip2 file. The content left compressed after these layers is parsed as is.
+    #[clap(long, value_name = "N", default_value = "3", value_parser = validate_decompress_depth_greater_than_zero, global = true)]
+    pub decompress_depth: usize,
+
+    /// Invoke every module in the configuration instead of stopping at the first match, and report all the formats that match the input.
+    #[clap(long, global = true)]
+    pub all_matches: bool,
+
+    /// Check the records against the semantics of the format beyond the syntax, such as the lengths of CIGAR and SEQ, the positions within `@SQ LN`, the order declared by `@HD SO`, the fields about the mates, and the read groups in `@RG` for SAM and BAM, and the declarations of INFO, FORMAT and contigs, the order by POS, and the number of samples for VCF and BCF. An input violating them is not detected as the format, with the number of the violations of each kind.
+    #[clap(long, global = true)]
+    pub strict: bool,
+
+    /// Expected format of the inputs, given as an EDAM ID or label. Use `<FILE|URL|'-'>=<EDAM ID|LABEL>` to specify it for a single input. Can be specified multiple times, and any of them is accepted.
+    /// If specified, tataki exits with 3 when no format is detected, 4 when a different format is detected, and 5 when the input cannot be read or downloaded.
+    #[clap(long, value_name = "EDAM ID|LABEL", value_parser = parse_expectation, global = true)]
+    pub expect: Vec<Expectation>,
+
+    /// Read the inputs from a file, or from STDIN with "-". The file is either a list of inputs, one per line, or a CSV/TSV with a `path` column and an optional `expect` column giving the expected format of each input, the same as `--expect`.
+    #[clap(long, value_name = "FILE|'-'", global = true)]
+    pub from_file: Option<String>,
+
+    /// Search the directories given as inputs recursively. Without this option, only the files directly under the directories are processed.
+    #[clap(short, long, global = true)]
+    pub recursive: bool,
+
+    /// Only process the files in the directories or matching the glob patterns given as inputs that match this glob pattern, e.g. '*.bam'. Can be specified multiple times.
+    #[clap(long, value_name = "GLOB", global = true)]
+    pub include: Vec<String>,
+
+    /// Skip the files in the directories or matching the glob patterns given as inputs that match this glob pattern. Can be specified multiple times.
+    #[clap(long, value_name = "GLOB", global = true)]
+    pub exclude: Vec<String>,
+
+    /// Follow symbolic links found in the directories given as inputs. Symbolic links given as inputs are always followed.
+    #[clap(long, global = true)]
+    pub follow_symlinks: bool,
+
+    /// Maximum number of bytes to decompress from an input, e.g. 10G. Decompression stops and the input is reported with the "limit_exceeded" status once exceeded. Accepts K, M, G and T suffixes in powers of 1024.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, global = true)]
+    pub max_decompressed_size: Option<u64>,
+
+    /// Maximum ratio of the decompressed size to the compressed size of an input, e.g. 100. Checked once 1 MiB has been decompressed, and the input is reported with the "limit_exceeded" status once exceeded.
+    #[clap(long, value_name = "RATIO", value_parser = validate_ratio_greater_than_zero, global = true)]
+    pub max_compression_ratio: Option<f64>,
+
+    /// Maximum number of bytes to download for an input given as a URL, e.g. 1G. The download stops and the input is reported with the "limit_exceeded" status once exceeded. Accepts K, M, G and T suffixes in powers of 1024.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, global = true)]
+    pub max_download_size: Option<u64>,
+
+    /// Maximum time in seconds to process an input, including downloading and decompressing it. The input is reported with the "limit_exceeded" status once exceeded.
+    #[clap(long, value_name = "SECONDS", value_parser = validate_timeout_greater_than_zero, global = true)]
+    pub input_timeout: Option<u64>,
+
+    /// Number of times to retry a download from a URL that has failed temporarily, such as by a dropped connection or HTTP 503, waiting 1, 2, 4, ... seconds in between. The download is resumed from where it stopped.
+    #[clap(long, value_name = "NUM", default_value = "3", global = true)]
+    pub retries: u32,
+
+    /// Maximum time in seconds for a request to a server to complete, including receiving the whole file [default: 3600]
+    #[clap(long, value_name = "SECONDS", value_parser = validate_timeout_greater_than_zero, global = true)]
+    pub request_timeout: Option<u64>,
+
+    /// Header to send with the requests to the servers, such as "X-Api-Key: <key>". Can be specified multiple times.
+    #[clap(long, value_name = "NAME: VALUE", value_parser = parse_header, global = true)]
+    pub header: Vec<(String, String)>,
+
+    /// Proxy for the requests to the servers, such as http://proxy.example.org:3128. Without this option, the proxy given by the HTTPS_PROXY, HTTP_PROXY and ALL_PROXY environment variables is used.
+    #[clap(long, value_name = "URL", global = true)]
+    pub proxy: Option<String>,
+
+    /// PEM file of the certificate authorities to trust in addition to the system ones, such as the one of an internal server.
+    #[clap(long, value_name = "FILE", global = true)]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// YAML file giving the headers, the proxy, the certificate authorities and the timeout for the requests, and the credentials for each host. Defaults to the file given by the TATAKI_HTTP_CONFIG environment variable.
+    #[clap(long, value_name = "FILE", global = true)]
+    pub http_config: Option<PathBuf>,
+
+    /// FASTA file of the reference sequences to decode CRAM records with, indexed by a `.fai` file next to it if one exists. The sequences not in it are looked up by their MD5 in REF_CACHE and REF_PATH, as samtools does.
+    #[clap(long, value_name = "FILE", global = true)]
+    pub reference: Option<PathBuf>,
+
     /// Number of records to read from the input file. Recommended to set it to a multiple of 4 to prevent false negatives. Conflicts with `--tidy` option.
-    #[clap(short, long, default_value = "100000", value_parser = validate_num_records_greater_than_zero)]
+    #[clap(short, long, default_value = "100000", value_parser = validate_num_records_greater_than_zero, global = true)]
     pub num_records: usize,
 
+    /// Number of inputs to process concurrently. The results are output in the order of the inputs regardless of this option.
+    #[clap(short, long, value_name = "N", default_value = "1", value_parser = validate_jobs_greater_than_zero, global = true)]
+    pub jobs: usize,
+
+    /// Invoke the built-in parsers concurrently for each input. The result is the same as invoking them one by one in the configured order, at the cost of running parsers that would have been skipped after the first match.
+    #[clap(long, global = true)]
+    pub parallel_modules: bool,
+
     /// Output the configuration file in yaml format and exit the program. If `--conf` option is not provided, the default configuration file will be shown.
     #[clap(long)]
     pub dry_run: bool,
 
     /// Show verbose log messages
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub verbose: bool,
 
     /// Suppress all log messages
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     pub quiet: bool,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check that the inputs are in the expected formats given by `--expect`, or in any format if not given, and print why the check failed.
+    Validate {
+        /// Path to the file, directory, glob pattern, URL, or "-" to read from standard input. Multiple inputs can be specified.
+        #[clap(name = "FILE|URL|'-'", required_unless_present = "from_file")]
+        input: Vec<String>,
+    },
+    /// Manage the downloads and the detected formats cached in the directory given by `--cache-dir`.
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// List the entries in the cache, the least recently used first.
+    List,
+    /// Remove the entries not used for `--older-than` days and the ones which can no longer be used, and then the least recently used ones until the cache fits in `--max-size`.
+    Prune {
+        /// Remove the entries not used for this number of days.
+        #[clap(long, value_name = "DAYS", default_value = "30")]
+        older_than: u64,
+        /// Maximum total size of the cache, e.g. 10G. Accepts K, M, G and T suffixes in powers of 1024.
+        #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+        max_size: Option<u64>,
+    },
+    /// Remove all the entries in the cache.
+    Clear,
+}
+
+// An expected format given by `--expect`, optionally bound to a single input.
+#[derive(Debug, Clone)]
+pub struct Expectation {
+    pub input: Option<String>,
+    pub format: String,
+}
+
 impl Args {
+    pub fn inputs(&self) -> &[String] {
+        match &self.command {
+            Some(Command::Validate { input }) => input,
+            Some(Command::Cache { .. }) => &[],
+            None => &self.input,
+        }
+    }
+
+    pub const fn is_validate(&self) -> bool {
+        matches!(self.command, Some(Command::Validate { .. }))
+    }
+
     pub const fn get_output_format(&self) -> OutputFormat {
         if self.yaml {
             OutputFormat::Yaml
@@ -99,3 +255,104 @@ fn validate_num_records_greater_than_zero(v: &str) -> Result<usize> {
     );
     Ok(num)
 }
+
+fn validate_jobs_greater_than_zero(v: &str) -> Result<usize> {
+    let num = v
+        .parse::<usize>()
+        .with_context(|| format!("Failed to parse {}", v))?;
+    anyhow::ensure!(num > 0, "The number of jobs must be greater than 0");
+    Ok(num)
+}
+
+fn validate_decompress_depth_greater_than_zero(v: &str) -> Result<usize> {
+    let num = v
+        .parse::<usize>()
+        .with_context(|| format!("Failed to parse {}", v))?;
+    anyhow::ensure!(
+        num > 0,
+        "The decompression depth must be greater than 0. Use `--no-decompress` to disable decompression."
+    );
+    Ok(num)
+}
+
+// parse a size in bytes such as "1048576", "512K" or "10G". The suffixes are in powers of 1024.
+fn parse_size(v: &str) -> Result<u64> {
+    let v = v.trim();
+    let (number, multiplier) = match v.char_indices().last() {
+        Some((index, suffix)) if suffix.is_ascii_alphabetic() => {
+            let exponent = match suffix.to_ascii_uppercase() {
+                'K' => 1,
+                'M' => 2,
+                'G' => 3,
+                'T' => 4,
+                _ => bail!(
+                    "Unknown size suffix '{}' in {}. Use K, M, G or T.",
+                    suffix,
+                    v
+                ),
+            };
+            (&v[..index], 1024u64.pow(exponent))
+        }
+        _ => (v, 1),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .with_context(|| format!("Failed to parse {}", v))?;
+    let size = number
+        .checked_mul(multiplier)
+        .with_context(|| format!("The size {} is too large", v))?;
+    anyhow::ensure!(size > 0, "The size must be greater than 0");
+    Ok(size)
+}
+
+fn validate_ratio_greater_than_zero(v: &str) -> Result<f64> {
+    let ratio = v
+        .parse::<f64>()
+        .with_context(|| format!("Failed to parse {}", v))?;
+    anyhow::ensure!(
+        ratio.is_finite() && ratio > 0.0,
+        "The compression ratio must be greater than 0"
+    );
+    Ok(ratio)
+}
+
+fn validate_timeout_greater_than_zero(v: &str) -> Result<u64> {
+    let seconds = v
+        .parse::<u64>()
+        .with_context(|| format!("Failed to parse {}", v))?;
+    anyhow::ensure!(seconds > 0, "The timeout must be greater than 0");
+    Ok(seconds)
+}
+
+// parse `<name>: <value>` given by `--header`.
+fn parse_header(v: &str) -> Result<(String, String)> {
+    let Some((name, value)) = v.split_once(':') else {
+        bail!("The header must be given as `<NAME>: <VALUE>`: {}", v);
+    };
+    anyhow::ensure!(
+        !name.trim().is_empty(),
+        "The header must be given as `<NAME>: <VALUE>`: {}",
+        v
+    );
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
+
+// parse `<EDAM ID|LABEL>` or `<FILE|URL|'-'>=<EDAM ID|LABEL>`. EDAM IDs and labels do not contain "=", so the last one separates the input from the format.
+fn parse_expectation(v: &str) -> Result<Expectation> {
+    let expectation = match v.rsplit_once('=') {
+        Some((input, format)) => Expectation {
+            input: Some(input.to_string()),
+            format: format.to_string(),
+        },
+        None => Expectation {
+            input: None,
+            format: v.to_string(),
+        },
+    };
+    anyhow::ensure!(
+        !expectation.format.is_empty() && expectation.input.as_ref().is_none_or(|i| !i.is_empty()),
+        "The expected format must be given as `<EDAM ID|LABEL>` or `<FILE|URL|'-'>=<EDAM ID|LABEL>`"
+    );
+    Ok(expectation)
+}
@@ -0,0 +1,463 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use log::{debug, info};
+use openssl::hash::{hash, Hasher, MessageDigest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+use crate::args::CacheCommand;
+use crate::module::ModuleResult;
+
+/// Name of the directory in `--cache-dir` holding the entries reused across runs, next to the temporary directories of the runs.
+const CACHE_DIR_NAME: &str = "cache";
+
+/// Name of the file describing a cached download, next to the downloaded file.
+const DOWNLOAD_ENTRY_NAME: &str = "entry.json";
+
+// The entries reused across the runs with the same `--cache-dir`. The time an entry has been used last is the modification time of its JSON file.
+// - `downloads/<key>/`: a file downloaded from a URL, keyed by the URL and the ETag or the modification time told by the server.
+// - `results/<key>.json`: the result determined for a content, keyed by the content and the fingerprint of the configuration and the options.
+// - `files/<key>.json`: the SHA-256 of a local file keyed by its path, reused while the size and the modification time of the file are unchanged.
+pub struct Cache {
+    root: PathBuf,
+    fingerprint: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDownload {
+    url: String,
+    validator: String,
+    file_name: String,
+}
+
+// `R` is a reference to the result when it is written.
+#[derive(Serialize, Deserialize)]
+struct CachedResult<R = ModuleResult> {
+    source: String,
+    fingerprint: String,
+    result: R,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    sha256: String,
+}
+
+#[derive(Clone, Copy)]
+enum EntryKind {
+    Download,
+    Result,
+    File,
+}
+
+impl EntryKind {
+    const ALL: [Self; 3] = [Self::Download, Self::Result, Self::File];
+
+    const fn dir_name(self) -> &'static str {
+        match self {
+            Self::Download => "downloads",
+            Self::Result => "results",
+            Self::File => "files",
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Result => "result",
+            Self::File => "file",
+        }
+    }
+}
+
+// An entry found in the cache, listed by `cache list` and removed by `cache prune`.
+struct Entry {
+    kind: EntryKind,
+    // the directory of a download, and the JSON file of the others.
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+    source: String,
+    detail: String,
+    // whether the entry can no longer be used, such as a file which has been removed or modified.
+    stale: bool,
+}
+
+impl Cache {
+    pub fn open(cache_dir: &Path, fingerprint: String) -> Result<Self> {
+        let root = cache_dir.join(CACHE_DIR_NAME);
+        for kind in EntryKind::ALL {
+            fs::create_dir_all(root.join(kind.dir_name()))
+                .with_context(|| format!("Failed to create the cache in {}", root.display()))?;
+        }
+        debug!("Using the cache in {}", root.display());
+        Ok(Self { root, fingerprint })
+    }
+
+    // The key of the content at the URL. It is only known to be unchanged while the server tells the same ETag or modification time.
+    pub fn url_key(url: &Url, validator: &str) -> Result<String> {
+        sha256_hex(format!("{}\n{}", url, validator).as_bytes())
+    }
+
+    // The key of the content of a local file, which is its SHA-256. The file is only read again once its size or modification time has changed.
+    pub fn file_key(&self, path: &Path) -> Result<String> {
+        let path = fs::canonicalize(path)?;
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata.modified()?;
+        let entry_path = self.entry_path(
+            EntryKind::File,
+            &sha256_hex(path.to_string_lossy().as_bytes())?,
+        );
+        if let Some(entry) = read_entry::<CachedFile>(&entry_path) {
+            if entry.path == path && entry.size == metadata.len() && entry.modified == modified {
+                touch(&entry_path);
+                return Ok(entry.sha256);
+            }
+        }
+
+        debug!("Computing the SHA-256 of {}", path.display());
+        let mut hasher = Hasher::new(MessageDigest::sha256())?;
+        io::copy(&mut File::open(&path)?, &mut hasher)?;
+        let sha256 = to_hex(&hasher.finish()?);
+        write_entry(
+            &entry_path,
+            &CachedFile {
+                path,
+                size: metadata.len(),
+                modified,
+                sha256: sha256.clone(),
+            },
+        )?;
+        Ok(sha256)
+    }
+
+    // The result determined for the content by a run with the same configuration and options, if any.
+    pub fn find_result(&self, content_key: &str) -> Option<ModuleResult> {
+        let entry_path = self.result_path(content_key).ok()?;
+        let entry = read_entry::<CachedResult>(&entry_path)?;
+        if entry.fingerprint != self.fingerprint {
+            return None;
+        }
+        touch(&entry_path);
+        Some(entry.result)
+    }
+
+    pub fn store_result(&self, content_key: &str, module_result: &ModuleResult) -> Result<()> {
+        write_entry(
+            &self.result_path(content_key)?,
+            &CachedResult {
+                source: module_result.input().to_string(),
+                fingerprint: self.fingerprint.clone(),
+                result: module_result,
+            },
+        )
+    }
+
+    // The file downloaded from the URL before, if any.
+    pub fn find_download(&self, url_key: &str) -> Option<PathBuf> {
+        let dir = self.entry_path(EntryKind::Download, url_key);
+        let entry_path = dir.join(DOWNLOAD_ENTRY_NAME);
+        let entry = read_entry::<CachedDownload>(&entry_path)?;
+        let path = dir.join(entry.file_name);
+        if !path.is_file() {
+            return None;
+        }
+        touch(&entry_path);
+        Some(path)
+    }
+
+    // Keep the file downloaded from the URL. The file is linked rather than copied if possible, so that it does not take up the space twice.
+    pub fn store_download(
+        &self,
+        url_key: &str,
+        url: &Url,
+        validator: &str,
+        path: &Path,
+    ) -> Result<()> {
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("No file name in {}", path.display()))?;
+        let dir = self.entry_path(EntryKind::Download, url_key);
+        fs::create_dir_all(&dir)?;
+        let cached_path = dir.join(file_name);
+        if !cached_path.exists() && fs::hard_link(path, &cached_path).is_err() {
+            // the file is copied under a temporary name, so that an incomplete copy is never taken for the download.
+            let mut temp_file = tempfile::NamedTempFile::new_in(&dir)?;
+            io::copy(&mut File::open(path)?, &mut temp_file)?;
+            temp_file.persist(&cached_path)?;
+        }
+        // the entry is written last, as a download without it is not found.
+        write_entry(
+            &dir.join(DOWNLOAD_ENTRY_NAME),
+            &CachedDownload {
+                url: url.to_string(),
+                validator: validator.to_string(),
+                file_name: file_name.to_string_lossy().into_owned(),
+            },
+        )
+    }
+
+    fn entry_path(&self, kind: EntryKind, key: &str) -> PathBuf {
+        let dir = self.root.join(kind.dir_name());
+        match kind {
+            EntryKind::Download => dir.join(key),
+            EntryKind::Result | EntryKind::File => dir.join(format!("{}.json", key)),
+        }
+    }
+
+    // Results are keyed by the fingerprint as well, so that the results of different configurations are cached side by side.
+    fn result_path(&self, content_key: &str) -> Result<PathBuf> {
+        Ok(self.entry_path(
+            EntryKind::Result,
+            &sha256_hex(format!("{}\n{}", content_key, self.fingerprint).as_bytes())?,
+        ))
+    }
+}
+
+// Run `tataki cache <list|prune|clear>` on the cache in `--cache-dir`.
+pub fn run_command(command: &CacheCommand, cache_dir: Option<&Path>) -> Result<()> {
+    let Some(cache_dir) = cache_dir else {
+        bail!("The `cache` subcommand requires `--cache-dir <DIR>` to locate the cache.");
+    };
+    let root = cache_dir.join(CACHE_DIR_NAME);
+
+    match command {
+        CacheCommand::List => {
+            let mut entries = list_entries(&root)?;
+            entries.sort_by_key(|entry| entry.last_used);
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "kind\tsize\tlast_used\tsource\tdetail")?;
+            for entry in &entries {
+                writeln!(
+                    stdout,
+                    "{}\t{}\t{}\t{}\t{}",
+                    entry.kind.name(),
+                    format_size(entry.size),
+                    DateTime::<Local>::from(entry.last_used).format("%Y-%m-%d %H:%M:%S"),
+                    entry.source,
+                    entry.detail
+                )?;
+            }
+            info!(
+                "{} entries, {} in total",
+                entries.len(),
+                format_size(entries.iter().map(|entry| entry.size).sum())
+            );
+        }
+        CacheCommand::Prune {
+            older_than,
+            max_size,
+        } => {
+            let mut entries = list_entries(&root)?;
+            let threshold = SystemTime::now()
+                .checked_sub(Duration::from_secs(older_than.saturating_mul(24 * 60 * 60)))
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let (mut to_remove, mut to_keep): (Vec<_>, Vec<_>) = entries
+                .drain(..)
+                .partition(|entry| entry.stale || entry.last_used < threshold);
+
+            // remove the least recently used entries until the rest fits in the size.
+            if let Some(max_size) = max_size {
+                to_keep.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+                let mut total_size = 0;
+                let fitting = to_keep
+                    .iter()
+                    .take_while(|entry| {
+                        total_size += entry.size;
+                        total_size <= *max_size
+                    })
+                    .count();
+                to_remove.extend(to_keep.drain(fitting..));
+            }
+
+            for entry in &to_remove {
+                remove_entry(entry)?;
+            }
+            println!(
+                "Removed {} entries ({}), and kept {} entries ({})",
+                to_remove.len(),
+                format_size(to_remove.iter().map(|entry| entry.size).sum()),
+                to_keep.len(),
+                format_size(to_keep.iter().map(|entry| entry.size).sum())
+            );
+        }
+        CacheCommand::Clear => {
+            let entries = list_entries(&root)?;
+            if root.exists() {
+                fs::remove_dir_all(&root)
+                    .with_context(|| format!("Failed to remove {}", root.display()))?;
+            }
+            println!(
+                "Removed {} entries ({})",
+                entries.len(),
+                format_size(entries.iter().map(|entry| entry.size).sum())
+            );
+        }
+    }
+    Ok(())
+}
+
+fn list_entries(root: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for kind in EntryKind::ALL {
+        let dir = root.join(kind.dir_name());
+        if !dir.is_dir() {
+            continue;
+        }
+        for dir_entry in fs::read_dir(&dir)? {
+            let path = dir_entry?.path();
+            // the files being written have temporary names without the extension.
+            if matches!(kind, EntryKind::Result | EntryKind::File)
+                && path.extension().is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+            entries.push(read_listed_entry(kind, path)?);
+        }
+    }
+    Ok(entries)
+}
+
+// Describe the entry. An entry which cannot be read, such as one written by another version, is listed as stale to be pruned.
+fn read_listed_entry(kind: EntryKind, path: PathBuf) -> Result<Entry> {
+    let entry_path = match kind {
+        EntryKind::Download => path.join(DOWNLOAD_ENTRY_NAME),
+        EntryKind::Result | EntryKind::File => path.clone(),
+    };
+    let last_used = fs::metadata(&entry_path)
+        .or_else(|_| fs::metadata(&path))
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut entry = Entry {
+        kind,
+        size: disk_size(&path)?,
+        path,
+        last_used,
+        source: String::new(),
+        detail: String::new(),
+        stale: true,
+    };
+
+    match kind {
+        EntryKind::Download => {
+            if let Some(download) = read_entry::<CachedDownload>(&entry_path) {
+                entry.stale = !entry.path.join(&download.file_name).is_file();
+                entry.source = download.url;
+                entry.detail = download.validator;
+            }
+        }
+        EntryKind::Result => {
+            if let Some(result) = read_entry::<CachedResult>(&entry_path) {
+                entry.stale = false;
+                entry.source = result.source;
+                entry.detail = result
+                    .result
+                    .label()
+                    .cloned()
+                    .or_else(|| result.result.error_message().cloned())
+                    .unwrap_or_default();
+            }
+        }
+        EntryKind::File => {
+            if let Some(file) = read_entry::<CachedFile>(&entry_path) {
+                entry.stale = fs::metadata(&file.path)
+                    .ok()
+                    .and_then(|metadata| Some((metadata.len(), metadata.modified().ok()?)))
+                    != Some((file.size, file.modified));
+                entry.source = file.path.display().to_string();
+                entry.detail = file.sha256;
+            }
+        }
+    }
+    Ok(entry)
+}
+
+fn remove_entry(entry: &Entry) -> Result<()> {
+    debug!("Removing {}", entry.path.display());
+    match entry.kind {
+        EntryKind::Download => fs::remove_dir_all(&entry.path),
+        EntryKind::Result | EntryKind::File => fs::remove_file(&entry.path),
+    }
+    .with_context(|| format!("Failed to remove {}", entry.path.display()))
+}
+
+fn disk_size(path: &Path) -> Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for dir_entry in fs::read_dir(path)? {
+        size += disk_size(&dir_entry?.path())?;
+    }
+    Ok(size)
+}
+
+// The entry in the file, or `None` if it does not exist or cannot be read, in which case it is not used.
+fn read_entry<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let content = fs::read(path).ok()?;
+    match serde_json::from_slice(&content) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            debug!("Ignoring the cache entry {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Write the entry under a temporary name and rename it, so that the runs in parallel never read a partially written entry.
+fn write_entry<T: Serialize>(path: &Path, entry: &T) -> Result<()> {
+    let dir = path
+        .parent()
+        .with_context(|| format!("No parent directory of {}", path.display()))?;
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    serde_json::to_writer(&mut temp_file, entry)?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+// Record that the entry has been used now. The entry is still usable without it.
+fn touch(path: &Path) {
+    if let Err(e) = File::options()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+    {
+        debug!("Failed to touch the cache entry {}: {}", path.display(), e);
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> Result<String> {
+    Ok(to_hex(&hash(MessageDigest::sha256(), bytes)?))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// format the size with the K, M, G and T suffixes in powers of 1024, the same as the sizes given to the options.
+fn format_size(size: u64) -> String {
+    const SUFFIXES: [&str; 4] = ["K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut suffix = "";
+    for next_suffix in SUFFIXES {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        suffix = next_suffix;
+    }
+    if suffix.is_empty() {
+        size.to_string()
+    } else {
+        format!("{:.1}{}", value, suffix)
+    }
+}
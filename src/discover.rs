@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use glob::Pattern;
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::args::Args;
+
+// An input to determine the format of, discovered from an argument given by the user.
+#[derive(Debug, Clone)]
+pub struct Input {
+    // file path, URL or "-"
+    pub path: String,
+    // the argument from which the input has been discovered, such as a directory or a glob pattern
+    pub origin: String,
+}
+
+pub struct DiscoverOptions {
+    /// Search directories recursively
+    pub recursive: bool,
+    /// Only keep the files that match any of these patterns
+    pub include: Vec<Pattern>,
+    /// Skip the files that match any of these patterns
+    pub exclude: Vec<Pattern>,
+    /// Follow symbolic links found in directories
+    pub follow_symlinks: bool,
+}
+
+impl TryFrom<&Args> for DiscoverOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<Pattern>> {
+            patterns
+                .iter()
+                .map(|p| Pattern::new(p).with_context(|| format!("Invalid glob pattern: {}", p)))
+                .collect()
+        };
+
+        Ok(Self {
+            recursive: args.recursive,
+            include: compile(&args.include)?,
+            exclude: compile(&args.exclude)?,
+            follow_symlinks: args.follow_symlinks,
+        })
+    }
+}
+
+impl DiscoverOptions {
+    // whether a file found in a directory or by a glob pattern is kept. `relative_path` is the path from the directory, or the path matched by the pattern.
+    fn is_selected(&self, relative_path: &Path) -> bool {
+        let included =
+            self.include.is_empty() || self.include.iter().any(|p| p.matches_path(relative_path));
+        let excluded = self.exclude.iter().any(|p| p.matches_path(relative_path));
+        included && !excluded
+    }
+}
+
+// Expand the arguments into the inputs to determine the format of. Directories are replaced with the files in them, and glob patterns with the matching files.
+// URLs, "-" and file paths are kept as is, so that the errors for missing files are reported in the same way as before.
+pub fn discover_inputs(arguments: &[String], options: &DiscoverOptions) -> Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+
+    for argument in arguments {
+        let url = Url::parse(argument).ok();
+        // a `file://` URL is handled as the local path it points to, so that a directory is expanded as well.
+        let local_path = url
+            .as_ref()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok());
+        let path = local_path.as_deref().unwrap_or(Path::new(argument));
+        let is_remote = url.is_some() && local_path.is_none();
+        let discovered = if argument == "-" || is_remote || path.is_file() {
+            vec![argument.clone()]
+        } else if path.is_dir() {
+            let mut ancestors = HashSet::new();
+            let mut files = Vec::new();
+            walk_directory(path, path, options, &mut ancestors, &mut files)?;
+            if files.is_empty() {
+                warn!(
+                    "No files to process were found in the directory {}",
+                    argument
+                );
+            }
+            files
+        } else if local_path.is_none() && is_glob_pattern(argument) {
+            let files = expand_glob_pattern(argument, options)?;
+            if files.is_empty() {
+                // keep the pattern, so that it is reported as a missing file.
+                warn!("No files match the pattern {}", argument);
+                vec![argument.clone()]
+            } else {
+                files
+            }
+        } else {
+            vec![argument.clone()]
+        };
+
+        debug!("Inputs found for {}: {:?}", argument, discovered);
+        inputs.extend(discovered.into_iter().map(|path| Input {
+            path,
+            origin: argument.clone(),
+        }));
+    }
+
+    Ok(inputs)
+}
+
+fn is_glob_pattern(argument: &str) -> bool {
+    argument.contains(['*', '?', '['])
+}
+
+fn expand_glob_pattern(pattern: &str, options: &DiscoverOptions) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in
+        glob::glob(pattern).with_context(|| format!("Invalid glob pattern: {}", pattern))?
+    {
+        let path = match entry {
+            // `glob` drops the leading "./" of the pattern, so restore it to report the inputs as the user wrote them.
+            Ok(path) if pattern.starts_with("./") => Path::new(".").join(path),
+            Ok(path) => path,
+            Err(e) => {
+                warn!(
+                    "Failed to read {}. Skipping it. Reason: {}",
+                    e.path().display(),
+                    e
+                );
+                continue;
+            }
+        };
+
+        if path.is_dir() {
+            let mut ancestors = HashSet::new();
+            walk_directory(&path, &path, options, &mut ancestors, &mut files)?;
+        } else if path.is_file() && options.is_selected(&path) {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(files)
+}
+
+// Collect the files in `dir` in the order of their names. Subdirectories are searched only with `--recursive`, and symbolic links are followed only with `--follow-symlinks`.
+fn walk_directory(
+    root: &Path,
+    dir: &Path,
+    options: &DiscoverOptions,
+    ancestors: &mut HashSet<PathBuf>,
+    files: &mut Vec<String>,
+) -> Result<()> {
+    // guard against symbolic links pointing to one of their parent directories.
+    let canonical_dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to read the directory {}", dir.display()))?;
+    if !ancestors.insert(canonical_dir.clone()) {
+        warn!(
+            "Skipping the directory linking to one of its parents: {}",
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read the directory {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(std::fs::DirEntry::file_name);
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() && !options.follow_symlinks {
+            debug!("Skipping the symbolic link: {}", path.display());
+            continue;
+        }
+
+        // `Path::is_dir` and `Path::is_file` follow symbolic links.
+        if path.is_dir() {
+            if options.recursive {
+                walk_directory(root, &path, options, ancestors, files)?;
+            }
+        } else if path.is_file() {
+            let relative_path = path.strip_prefix(root).unwrap_or(&path);
+            if options.is_selected(relative_path) {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        } else if file_type.is_symlink() {
+            warn!("Skipping the broken symbolic link: {}", path.display());
+        }
+    }
+
+    ancestors.remove(&canonical_dir);
+    Ok(())
+}
@@ -47,6 +47,25 @@ impl EdamMap {
         id.cloned()
     }
 
+    // resolve the given EDAM ID, its short form (e.g. "format_2573") or label into a pair of ID and label.
+    // labels are compared case-insensitively.
+    pub fn resolve(&self, id_or_label: &str) -> Option<(String, String)> {
+        let id = if id_or_label.starts_with("format_") {
+            format!("http://edamontology.org/{}", id_or_label)
+        } else {
+            id_or_label.to_string()
+        };
+
+        if let Some(label) = self.bimap_id_label.get_by_left(&id) {
+            return Some((id, label.clone()));
+        }
+
+        self.bimap_id_label
+            .iter()
+            .find(|(_, label)| label.eq_ignore_ascii_case(id_or_label))
+            .map(|(id, label)| (id.clone(), label.clone()))
+    }
+
     // check if the given pair of id and label exists in the EDAM table.
     pub fn correspondence_check_id_and_label(&self, id: &str, label: &str) -> Result<bool> {
         let label_from_bimap = self.bimap_id_label.get_by_left(id);
@@ -1,11 +1,154 @@
-use anyhow::{ensure, Result};
+mod client;
+mod drs;
+mod ftp;
+mod gcs;
+mod htsget;
+mod http;
+mod s3;
+
+use anyhow::{anyhow, bail, Result};
 use chrono::Local;
-use std::path::PathBuf;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time;
-use std::{fs::File, io::Write};
 use tempfile::TempDir;
 use url::Url;
 
+use crate::args::Args;
+use crate::limits::{find_limit_exceeded, Deadline, DeadlineReader, LimitExceeded};
+use crate::module::InvokeOptions;
+
+pub use client::{Client, HttpSettings};
+
+/// Wait before the first retry of a failed request, which is doubled for each of the following retries.
+const RETRY_INITIAL_DELAY: time::Duration = time::Duration::from_secs(1);
+
+/// Longest wait between the retries.
+const RETRY_MAX_DELAY: time::Duration = time::Duration::from_secs(60);
+
+pub trait Fetcher {
+    /// Starts reading the content at `url` from the byte at `offset`, which is nonzero when a partial download is resumed.
+    /// Credentials are taken from the environment variables of the service, or from the ones given for the host by `--http-config` through `client`, and the content is read anonymously without them.
+    /// Resolve the URL into the one of the content and call `open_remote()` with it if the URL points to a description of the content, such as a DRS object.
+    /// Call `skip()` if the service cannot start from the middle of the content.
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote>;
+
+    /// Reads the last `length` bytes of the content at `url`, or the whole content if it is shorter, such as to check the end of a BGZF file without downloading it.
+    /// Returns `None` if the service cannot start from the middle of the content, so that the content is not read as a whole only for its end.
+    fn read_tail(&self, _url: &Url, _client: &Client, _length: u64) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+// The content of a remote file being received.
+pub struct Remote {
+    pub reader: Box<dyn Read>,
+    /// Size of the content from the offset if the server tells it
+    pub content_length: Option<u64>,
+    /// ETag or last modified time of the content if the server tells it, which tells whether the content has changed since a partial download
+    pub validator: Option<String>,
+}
+
+// Options for the inputs given as URLs.
+#[derive(Debug, Clone, Default)]
+pub struct FetchOptions {
+    /// Number of times to retry a failed download
+    pub retries: u32,
+    /// Directory in which the partial downloads of the previous runs are resumed
+    pub cache_dir: Option<PathBuf>,
+    /// Headers, credentials, proxy, certificate authorities and timeout of the requests
+    pub http: Arc<HttpSettings>,
+}
+
+impl TryFrom<&Args> for FetchOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        Ok(Self {
+            retries: args.retries,
+            cache_dir: args.cache_dir.clone(),
+            http: Arc::new(HttpSettings::try_from(args)?),
+        })
+    }
+}
+
+// The error returned when the server refuses a request. The request is retried if the server tells that the failure is temporary, such as HTTP 503 or FTP 421.
+#[derive(Debug)]
+pub struct FetchError {
+    message: String,
+    transient: bool,
+}
+
+impl FetchError {
+    pub fn new(message: impl Into<String>, transient: bool) -> Self {
+        Self {
+            message: message.into(),
+            transient,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+pub fn from_scheme_to_fetcher(scheme: &str) -> Result<Box<dyn Fetcher>> {
+    match scheme {
+        "http" | "https" => Ok(Box::new(http::Http)),
+        "ftp" => Ok(Box::new(ftp::Ftp)),
+        "s3" => Ok(Box::new(s3::S3)),
+        "gs" => Ok(Box::new(gcs::Gcs)),
+        "drs" => Ok(Box::new(drs::Drs)),
+        "htsget" => Ok(Box::new(htsget::Htsget)),
+        _ => bail!(
+            "Unsupported URL scheme: {}. Supported schemes are http, https, ftp, s3, gs, drs and htsget, as well as file for local files.",
+            scheme
+        ),
+    }
+}
+
+// Start reading the content at the URL with the fetcher for its scheme.
+pub fn open_remote(url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+    from_scheme_to_fetcher(url.scheme())?.open(url, client, offset)
+}
+
+// Read the end of the content at the URL with the fetcher for its scheme.
+pub fn read_remote_tail(url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+    from_scheme_to_fetcher(url.scheme())?.read_tail(url, client, length)
+}
+
+// Skip the first `offset` bytes of the content read from the beginning, for the services that cannot start from the middle of it.
+pub fn skip(mut remote: Remote, offset: u64) -> Result<Remote> {
+    let skipped = io::copy(&mut (&mut remote.reader).take(offset), &mut io::sink())?;
+    if skipped < offset {
+        bail!(FetchError::new(
+            format!(
+                "The content is shorter than the {} bytes downloaded before",
+                offset
+            ),
+            false
+        ));
+    }
+    remote.content_length = remote
+        .content_length
+        .map(|length| length.saturating_sub(offset));
+    Ok(remote)
+}
+
+// The local path of a `file://` URL, which is read in the same way as the path.
+pub fn file_url_to_path(url: &Url) -> Result<PathBuf> {
+    url.to_file_path()
+        .map_err(|()| anyhow!("The file URL does not point to a local file: {}", url))
+}
+
 pub fn create_temporary_dir(cache_dir: &Option<PathBuf>) -> Result<TempDir> {
     let prefix = format!("tataki_{}_", Local::now().format("%Y-%m%d-%H%M%S"));
 
@@ -22,30 +165,440 @@ pub fn create_temporary_dir(cache_dir: &Option<PathBuf>) -> Result<TempDir> {
     }
 }
 
-pub fn download_from_url(url: &Url, temp_dir: &TempDir) -> Result<PathBuf> {
-    // timeout in 60 * 60 seconds
-    let client = reqwest::blocking::Client::builder()
-        .timeout(time::Duration::from_secs(3600))
-        .build()?;
-    let response = client.get(url.as_ref()).send()?;
-    let status = response.status();
-    let response_bytes = response.bytes()?;
+// The record of a partial download, kept as `<name>.part.json` next to `<name>.part` so that a later run with the same `--cache-dir` resumes it.
+#[derive(Serialize, Deserialize)]
+struct PartialDownload {
+    url: String,
+    /// Size of the whole content if the server has told it
+    content_length: Option<u64>,
+    validator: Option<String>,
+}
 
-    ensure!(
-        status.is_success(),
-        "Failed to download from {} with status code {}",
-        url.as_str(),
-        status
-    );
+// Waits between the attempts of a request, doubling the wait each time.
+struct Backoff {
+    retries_left: u32,
+    delay: time::Duration,
+    deadline: Option<Deadline>,
+}
+
+impl Backoff {
+    fn new(options: &InvokeOptions) -> Self {
+        Self {
+            retries_left: options.fetch.retries,
+            delay: RETRY_INITIAL_DELAY,
+            deadline: options.deadline,
+        }
+    }
+
+    // Wait before retrying the request that has failed with `error`, or return false if it is not worth retrying.
+    fn wait(&mut self, url: &Url, error: &anyhow::Error) -> bool {
+        if self.retries_left == 0 || !is_transient(error) {
+            return false;
+        }
+        // give up rather than waiting beyond the time left for the input.
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline.remaining() <= self.delay)
+        {
+            return false;
+        }
+
+        warn!(
+            "Failed to download from {}: {:#}. Retrying in {} seconds...",
+            url,
+            error,
+            self.delay.as_secs()
+        );
+        std::thread::sleep(self.delay);
+        self.retries_left -= 1;
+        self.delay = (self.delay * 2).min(RETRY_MAX_DELAY);
+        true
+    }
+}
+
+// Whether the failure is worth retrying: the connection has failed or timed out, or the server has told that the failure is temporary.
+fn is_transient(error: &anyhow::Error) -> bool {
+    find_limit_exceeded(error).is_none() && error.chain().any(is_transient_cause)
+}
+
+fn is_transient_cause(cause: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(error) = cause.downcast_ref::<FetchError>() {
+        return error.transient;
+    }
+    if let Some(error) = cause.downcast_ref::<reqwest::Error>() {
+        return error.is_connect() || error.is_timeout() || error.is_request() || error.is_body();
+    }
+    let Some(error) = cause.downcast_ref::<io::Error>() else {
+        return false;
+    };
+    match error.get_ref() {
+        // readers wrap the errors of the requests in `io::Error`.
+        Some(inner) if inner.is::<FetchError>() || inner.is::<reqwest::Error>() => {
+            is_transient_cause(inner)
+        }
+        _ => matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::Interrupted
+        ),
+    }
+}
+
+// A response being downloaded into `<name>.part`. The content is written to the file as it is read, so that reading only the beginning of a large file, such as for sampling, downloads only that part.
+// The file is renamed to `<name>` once the whole content has been downloaded. A request failing temporarily is retried, and the download is resumed from where it stopped.
+// The file is removed if the download is dropped without `keep()`, unless it has failed and can be resumed by a later run.
+// A download resumed from a previous run is read from the beginning as well, with the bytes downloaded before read back from the file.
+pub struct Download {
+    url: Url,
+    client: Client,
+    response: DeadlineReader<Box<dyn Read>>,
+    file: File,
+    // the bytes downloaded by a previous run, which are read before the response.
+    resumed: Option<io::Take<File>>,
+    path: PathBuf,
+    bytes_read: u64,
+    content_length: Option<u64>,
+    validator: Option<String>,
+    finished: bool,
+    max_download_size: Option<u64>,
+    deadline: Option<Deadline>,
+    backoff: Backoff,
+    kept: bool,
+    interrupted: bool,
+}
+
+// Start downloading from the URL. Nothing but the response headers is received until the download is read.
+pub fn open_url(url: &Url, temp_dir: &TempDir, options: &InvokeOptions) -> Result<Download> {
+    // timeout in `--request-timeout`, or the time left for the input if `--input-timeout` is shorter.
+    let timeout = options
+        .deadline
+        .map_or(options.fetch.http.timeout, |deadline| {
+            deadline.remaining().min(options.fetch.http.timeout)
+        });
+    let client = Client::new(&options.fetch.http, timeout)?;
 
     // write the content of the response to a temporary file
     let file_name = &url
         .path_segments()
         .and_then(|mut segments| segments.next_back())
+        .filter(|segment| !segment.is_empty())
         .unwrap_or("downloaded_file");
-    let file_path = temp_dir.path().join(file_name);
-    let mut file = File::create(&file_path)?;
-    file.write_all(&response_bytes)?;
+    let path = create_part_file(temp_dir, file_name)?;
+    // resume the download of the same URL left by a previous run in the cache directory.
+    let partial = options
+        .fetch
+        .cache_dir
+        .as_ref()
+        .and_then(|cache_dir| take_partial_download(cache_dir, temp_dir.path(), url, &path));
+    let file = File::options().append(true).open(&path)?;
+    let bytes_read = file.metadata()?.len();
+
+    let mut download = Download {
+        url: url.clone(),
+        client,
+        response: DeadlineReader::new(Box::new(io::empty()), options.deadline),
+        file,
+        resumed: None,
+        path,
+        bytes_read,
+        content_length: None,
+        validator: None,
+        finished: false,
+        max_download_size: options.limits.max_download_size,
+        deadline: options.deadline,
+        backoff: Backoff::new(options),
+        kept: false,
+        interrupted: false,
+    };
+    if let Err(e) = download.start(partial.as_ref()) {
+        download.interrupted = find_limit_exceeded(&e).is_none();
+        return Err(e);
+    }
+    Ok(download)
+}
+
+// Create an empty `<file name>.part` in the temporary directory.
+fn create_part_file(temp_dir: &TempDir, file_name: &str) -> Result<PathBuf> {
+    let part_name = format!("{}.part", file_name);
+    let path = temp_dir.path().join(&part_name);
+    // inputs may be downloaded concurrently with `--jobs`, so a file downloaded for another input with the same name must not be overwritten.
+    if !temp_dir.path().join(file_name).exists() {
+        match File::create_new(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let sub_dir = tempfile::Builder::new()
+        .prefix("download_")
+        .tempdir_in(temp_dir.path())?
+        .keep();
+    let path = sub_dir.join(part_name);
+    File::create(&path)?;
+    Ok(path)
+}
+
+// The path of the record of the partial download at `part_path`.
+fn record_path(part_path: &Path) -> PathBuf {
+    let mut path = part_path.as_os_str().to_owned();
+    path.push(".json");
+    PathBuf::from(path)
+}
+
+// Move the largest partial download of the URL left by the previous runs in the cache directory to `path`.
+fn take_partial_download(
+    cache_dir: &Path,
+    temp_dir: &Path,
+    url: &Url,
+    path: &Path,
+) -> Option<PartialDownload> {
+    let pattern = format!(
+        "{}/tataki_*/**/*.part.json",
+        glob::Pattern::escape(&cache_dir.to_string_lossy())
+    );
+    // the downloads in the temporary directory of the current run may still be in progress.
+    let temp_dir = std::fs::canonicalize(temp_dir).ok()?;
+    let (found_record_path, record) = glob::glob(&pattern)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|record_path| {
+            std::fs::canonicalize(record_path).is_ok_and(|path| !path.starts_with(&temp_dir))
+        })
+        .filter_map(|record_path| {
+            let record: PartialDownload =
+                serde_json::from_slice(&std::fs::read(&record_path).ok()?).ok()?;
+            (record.url == url.as_str()).then_some((record_path, record))
+        })
+        .max_by_key(|(record_path, _)| {
+            std::fs::metadata(record_path.with_extension("")).map_or(0, |metadata| metadata.len())
+        })?;
+
+    let found_path = found_record_path.with_extension("");
+    std::fs::rename(&found_path, path).ok()?;
+    std::fs::rename(&found_record_path, record_path(path)).ok();
+    debug!(
+        "Found the partial download of {} at {}",
+        url,
+        found_path.display()
+    );
+    Some(record)
+}
+
+impl Download {
+    // Send the request, resuming the partial download if the content has not changed since.
+    fn start(&mut self, partial: Option<&PartialDownload>) -> Result<()> {
+        let remote = self.request();
+        // report the timeout of the input rather than the one of the request.
+        if let Some(deadline) = &self.deadline {
+            deadline.check()?;
+        }
+        let mut remote = remote?;
+
+        if self.bytes_read > 0 {
+            let content_length = remote.content_length.map(|length| length + self.bytes_read);
+            let unchanged = partial.is_some_and(|partial| {
+                (partial.validator.is_none() || partial.validator == remote.validator)
+                    && (partial.content_length.is_none()
+                        || partial.content_length == content_length)
+            });
+            if unchanged {
+                info!(
+                    "Resuming the download from {} at {} bytes",
+                    self.url, self.bytes_read
+                );
+                self.resumed = Some(File::open(&self.path)?.take(self.bytes_read));
+            } else {
+                info!(
+                    "Downloading from {} from the beginning, as the content has changed since the partial download",
+                    self.url
+                );
+                self.file.set_len(0)?;
+                self.bytes_read = 0;
+                remote = self.request()?;
+            }
+        }
+
+        self.content_length = remote.content_length.map(|length| length + self.bytes_read);
+        // refuse the download up front if the server tells the size.
+        if let (Some(max), Some(length)) = (self.max_download_size, self.content_length) {
+            if length > max {
+                return Err(exceeds_max_download_size(max).into());
+            }
+        }
+        self.validator = remote.validator;
+        self.response = DeadlineReader::new(remote.reader, self.deadline);
+
+        let record = PartialDownload {
+            url: self.url.to_string(),
+            content_length: self.content_length,
+            validator: self.validator.clone(),
+        };
+        std::fs::write(record_path(&self.path), serde_json::to_vec(&record)?)?;
+        Ok(())
+    }
+
+    // Open the content from the bytes downloaded so far, retrying the temporary failures.
+    fn request(&mut self) -> Result<Remote> {
+        loop {
+            match open_remote(&self.url, &self.client, self.bytes_read) {
+                Ok(remote) => return Ok(remote),
+                Err(e) if self.backoff.wait(&self.url, &e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Read the response, resuming it if it fails in the middle.
+    fn read_response(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let error = match self.response.read(buf) {
+                // the connection closed before the size told by the server is also resumed.
+                Ok(0)
+                    if !buf.is_empty()
+                        && self
+                            .content_length
+                            .is_some_and(|length| self.bytes_read < length) =>
+                {
+                    anyhow::Error::from(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "The connection was closed after {} of {} bytes",
+                            self.bytes_read,
+                            self.content_length.unwrap_or_default()
+                        ),
+                    ))
+                }
+                Ok(bytes_read) => return Ok(bytes_read),
+                Err(e) => anyhow::Error::from(e),
+            };
+            if !self.backoff.wait(&self.url, &error) {
+                return Err(error);
+            }
+
+            let remote = self.request()?;
+            if let (Some(validator), Some(new_validator)) = (&self.validator, &remote.validator) {
+                if validator != new_validator {
+                    bail!(FetchError::new(
+                        format!(
+                            "The content at {} has changed during the download",
+                            self.url
+                        ),
+                        false
+                    ));
+                }
+            }
+            info!(
+                "Resuming the download from {} at {} bytes",
+                self.url, self.bytes_read
+            );
+            self.response = DeadlineReader::new(remote.reader, self.deadline);
+        }
+    }
+
+    // The ETag or the modification time of the content told by the server, which tells whether the content has changed.
+    pub fn validator(&self) -> Option<&str> {
+        self.validator.as_deref()
+    }
+
+    // Read the last `length` bytes of the content, from the downloaded file if the whole content has been downloaded, and with a separate request otherwise.
+    // Returns `None` if the server cannot send only the end.
+    pub fn read_tail(&mut self, length: u64) -> Result<Option<Vec<u8>>> {
+        if !self.is_complete() {
+            return read_remote_tail(&self.url, &self.client, length);
+        }
+        let mut file = File::open(&self.path)?;
+        file.seek(io::SeekFrom::Start(self.bytes_read.saturating_sub(length)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+        Ok(Some(tail))
+    }
+
+    // whether the whole content has been downloaded.
+    fn is_complete(&self) -> bool {
+        self.finished || self.content_length == Some(self.bytes_read)
+    }
+
+    // Stop downloading and keep the file. The file is renamed from `<name>.part` to `<name>` if the whole content has been downloaded, and is left as `<name>.part` to be resumed otherwise.
+    pub fn keep(mut self) -> Result<PathBuf> {
+        self.kept = true;
+        if !self.is_complete() {
+            info!(
+                "Stopped downloading after {} bytes as they are enough to determine the format",
+                self.bytes_read
+            );
+            return Ok(self.path.clone());
+        }
+
+        std::fs::remove_file(record_path(&self.path)).ok();
+        let path = self.path.with_extension("");
+        std::fs::rename(&self.path, &path)?;
+        Ok(path)
+    }
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(resumed) = self.resumed.as_mut() {
+            let bytes_read = resumed.read(buf)?;
+            if bytes_read > 0 || buf.is_empty() {
+                return Ok(bytes_read);
+            }
+            self.resumed = None;
+        }
+
+        // read one byte more than the limit to tell whether the content exceeds it.
+        let max_to_read = self.max_download_size.map_or(u64::MAX, |max| {
+            max.saturating_add(1).saturating_sub(self.bytes_read)
+        });
+        let len = buf
+            .len()
+            .min(usize::try_from(max_to_read).unwrap_or(usize::MAX));
+
+        let bytes_read = match self.read_response(&mut buf[..len]) {
+            Ok(bytes_read) => bytes_read,
+            Err(e) => {
+                self.interrupted = find_limit_exceeded(&e).is_none();
+                return Err(match e.downcast::<io::Error>() {
+                    Ok(e) => e,
+                    Err(e) => io::Error::other(e),
+                });
+            }
+        };
+        if bytes_read == 0 && len > 0 {
+            self.finished = true;
+        }
+        self.bytes_read += bytes_read as u64;
+        if let Some(max) = self.max_download_size {
+            if self.bytes_read > max {
+                return Err(exceeds_max_download_size(max).into_io_error());
+            }
+        }
+
+        self.file.write_all(&buf[..bytes_read])?;
+        Ok(bytes_read)
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        // keep a failed download to be resumed by a later run in the same cache directory, and do not leave anything else there.
+        if !(self.kept || self.interrupted && self.bytes_read > 0) {
+            std::fs::remove_file(&self.path).ok();
+            std::fs::remove_file(record_path(&self.path)).ok();
+        }
+    }
+}
 
-    Ok(file_path)
+fn exceeds_max_download_size(max: u64) -> LimitExceeded {
+    LimitExceeded::new(format!(
+        "The download size exceeds {} bytes given by `--max-download-size`",
+        max
+    ))
 }
@@ -0,0 +1,337 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::{Certificate, NoProxy, Proxy};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+use crate::args::Args;
+
+/// Time for a request to complete unless `--request-timeout` or `timeout` in `--http-config` is given.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Environment variable giving the file of `--http-config` if the option is not given.
+const HTTP_CONFIG_ENV: &str = "TATAKI_HTTP_CONFIG";
+
+// The file given by `--http-config`. The values may refer to environment variables as `${NAME}`, so that the secrets are kept out of the file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HttpConfigFile {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    proxy: Option<String>,
+    ca_bundle: Option<PathBuf>,
+    timeout: Option<u64>,
+    // keyed by `<host>`, `<host>:<port>` or `*.<domain>`
+    #[serde(default)]
+    hosts: BTreeMap<String, HostConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostConfig {
+    bearer_token: Option<String>,
+    basic_auth: Option<BasicAuth>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BasicAuth {
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+// the password is left out of the logs.
+impl std::fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+// The settings of the requests to the servers, given by `--http-config`, `--header`, `--proxy`, `--ca-bundle` and `--request-timeout`.
+// The options take precedence over the file. The header values are marked as sensitive, so that they are left out of the logs.
+#[derive(Debug, Clone)]
+pub struct HttpSettings {
+    /// Headers sent to every host
+    headers: HeaderMap,
+    /// Headers and credentials for the hosts, the most specific pattern first
+    hosts: Vec<(String, HostSettings)>,
+    proxy: Option<Proxy>,
+    certificates: Vec<Certificate>,
+    /// Time for a request to complete
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct HostSettings {
+    headers: HeaderMap,
+    basic_auth: Option<BasicAuth>,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            headers: HeaderMap::new(),
+            hosts: Vec::new(),
+            proxy: None,
+            certificates: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl TryFrom<&Args> for HttpSettings {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        let config_path = args
+            .http_config
+            .clone()
+            .or_else(|| std::env::var_os(HTTP_CONFIG_ENV).map(PathBuf::from));
+        let mut config = match &config_path {
+            Some(path) => read_config(path)?,
+            None => HttpConfigFile::default(),
+        };
+        for (name, value) in &args.header {
+            config.headers.insert(name.clone(), value.clone());
+        }
+        if let Some(proxy) = &args.proxy {
+            config.proxy = Some(proxy.clone());
+        }
+        if let Some(ca_bundle) = &args.ca_bundle {
+            config.ca_bundle = Some(ca_bundle.clone());
+        }
+        if let Some(timeout) = args.request_timeout {
+            config.timeout = Some(timeout);
+        }
+
+        let mut hosts = Vec::new();
+        for (pattern, host_config) in config.hosts {
+            let mut headers = to_header_map(&host_config.headers)?;
+            let authorization = match (&host_config.bearer_token, &host_config.basic_auth) {
+                (Some(_), Some(_)) => bail!(
+                    "Both `bearer_token` and `basic_auth` are given for the host {} in the HTTP config",
+                    pattern
+                ),
+                (Some(token), None) => Some(format!("Bearer {}", token)),
+                (None, Some(basic_auth)) => Some(format!(
+                    "Basic {}",
+                    openssl::base64::encode_block(
+                        format!("{}:{}", basic_auth.username, basic_auth.password).as_bytes()
+                    )
+                )),
+                (None, None) => None,
+            };
+            if let Some(authorization) = authorization {
+                headers.insert(
+                    AUTHORIZATION,
+                    sensitive(&authorization).with_context(|| {
+                        format!(
+                            "Invalid credentials for the host {} in the HTTP config",
+                            pattern
+                        )
+                    })?,
+                );
+            }
+            hosts.push((
+                pattern.to_ascii_lowercase(),
+                HostSettings {
+                    headers,
+                    basic_auth: host_config.basic_auth,
+                },
+            ));
+        }
+        // the exact hosts come before the wildcards, and the longer wildcards before the shorter ones.
+        hosts.sort_by_key(|(pattern, _)| {
+            (pattern.starts_with("*."), std::cmp::Reverse(pattern.len()))
+        });
+
+        // the hosts in NO_PROXY are reached directly with the proxy given as well.
+        let proxy = config
+            .proxy
+            .map(|proxy| {
+                Proxy::all(&proxy)
+                    .map(|proxy| proxy.no_proxy(NoProxy::from_env()))
+                    .with_context(|| format!("Invalid proxy: {}", proxy))
+            })
+            .transpose()?;
+        let certificates = match &config.ca_bundle {
+            Some(path) => read_ca_bundle(path)?,
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            headers: to_header_map(&config.headers)?,
+            hosts,
+            proxy,
+            certificates,
+            timeout: config.timeout.map_or(DEFAULT_TIMEOUT, Duration::from_secs),
+        })
+    }
+}
+
+impl HttpSettings {
+    // The settings for the host of the URL, matched by `<host>:<port>`, `<host>` and then `*.<domain>`.
+    fn for_host(&self, url: &Url) -> Option<&HostSettings> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let host_with_port = url
+            .port_or_known_default()
+            .map(|port| format!("{}:{}", host, port));
+        self.hosts.iter().find_map(|(pattern, settings)| {
+            let matches = match pattern.strip_prefix("*.") {
+                Some(domain) => host.ends_with(&format!(".{}", domain)),
+                None => *pattern == host || Some(pattern) == host_with_port.as_ref(),
+            };
+            matches.then_some(settings)
+        })
+    }
+}
+
+// The HTTP client shared by the fetchers, which sends the headers and the credentials given by `HttpSettings` to the matching hosts.
+// The headers given by a fetcher, such as a signature for S3, take precedence over them.
+#[derive(Clone)]
+pub struct Client {
+    inner: reqwest::blocking::Client,
+    settings: Arc<HttpSettings>,
+    timeout: Duration,
+}
+
+impl Client {
+    pub fn new(settings: &Arc<HttpSettings>, timeout: Duration) -> Result<Self> {
+        let mut builder = reqwest::blocking::Client::builder().timeout(timeout);
+        if let Some(proxy) = &settings.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &settings.certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        Ok(Self {
+            inner: builder.build()?,
+            settings: settings.clone(),
+            timeout,
+        })
+    }
+
+    // The time for a request to complete, for the protocols other than HTTP such as FTP.
+    pub const fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Whether a proxy has been given by `--proxy` or `--http-config`, which the protocols other than HTTP cannot be sent through.
+    pub fn has_proxy(&self) -> bool {
+        self.settings.proxy.is_some()
+    }
+
+    pub fn get(&self, url: &Url) -> RequestBuilder {
+        self.with_settings(url, self.inner.get(url.as_ref()))
+    }
+
+    pub fn post(&self, url: &Url) -> RequestBuilder {
+        self.with_settings(url, self.inner.post(url.as_ref()))
+    }
+
+    // The user and the password given for the host, for the protocols other than HTTP such as FTP.
+    pub fn basic_auth(&self, url: &Url) -> Option<&BasicAuth> {
+        self.settings.for_host(url)?.basic_auth.as_ref()
+    }
+
+    fn with_settings(&self, url: &Url, request: RequestBuilder) -> RequestBuilder {
+        let request = request.headers(self.settings.headers.clone());
+        match self.settings.for_host(url) {
+            Some(host_settings) => {
+                debug!("Sending the headers given for the host of {}", url);
+                request.headers(host_settings.headers.clone())
+            }
+            None => request,
+        }
+    }
+}
+
+fn read_config(path: &Path) -> Result<HttpConfigFile> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the HTTP config: {}", path.display()))?;
+    let mut config: HttpConfigFile = serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse the HTTP config: {}", path.display()))?;
+
+    let expand = |value: &mut String| -> Result<()> {
+        *value = expand_env(value)
+            .with_context(|| format!("Failed to read the HTTP config: {}", path.display()))?;
+        Ok(())
+    };
+    config.headers.values_mut().try_for_each(expand)?;
+    if let Some(proxy) = config.proxy.as_mut() {
+        expand(proxy)?;
+    }
+    for host_config in config.hosts.values_mut() {
+        host_config.headers.values_mut().try_for_each(expand)?;
+        if let Some(token) = host_config.bearer_token.as_mut() {
+            expand(token)?;
+        }
+        if let Some(basic_auth) = host_config.basic_auth.as_mut() {
+            expand(&mut basic_auth.username)?;
+            expand(&mut basic_auth.password)?;
+        }
+    }
+    Ok(config)
+}
+
+// Replace `${NAME}` with the value of the environment variable.
+fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start..].find('}') else {
+            bail!("Unclosed `${{` in {}", value);
+        };
+        let name = &rest[start + 2..start + length];
+        let env_value = std::env::var(name)
+            .with_context(|| format!("The environment variable {} is not set", name))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&env_value);
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn read_ca_bundle(path: &Path) -> Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)
+        .with_context(|| format!("Failed to read the CA bundle: {}", path.display()))?;
+    let certificates = Certificate::from_pem_bundle(&pem)
+        .with_context(|| format!("Failed to parse the CA bundle: {}", path.display()))?;
+    if certificates.is_empty() {
+        bail!(
+            "No certificate is found in the CA bundle: {}",
+            path.display()
+        );
+    }
+    Ok(certificates)
+}
+
+fn to_header_map(headers: &BTreeMap<String, String>) -> Result<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        header_map.insert(
+            HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name: {}", name))?,
+            sensitive(value).with_context(|| format!("Invalid value of the header {}", name))?,
+        );
+    }
+    Ok(header_map)
+}
+
+fn sensitive(value: &str) -> Result<HeaderValue> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
@@ -0,0 +1,128 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use url::Url;
+
+use crate::fetch::http::{api_base_url, bearer_token_from_env, get, get_json, get_tail};
+use crate::fetch::{open_remote, read_remote_tail, Client, Fetcher, Remote};
+
+// Fetch `drs://<host>/<object id>` by resolving it into one of the access methods of the GA4GH DRS object.
+// The DRS API is accessed with the bearer token in `TATAKI_DRS_BEARER_TOKEN` if it is set, and the access URLs in `s3://` and `gs://` are fetched with the credentials for them.
+pub struct Drs;
+
+// The types of the access methods in the order of preference. The others, such as `gsiftp`, are not supported.
+const SUPPORTED_ACCESS_TYPES: [&str; 5] = ["https", "http", "s3", "gs", "ftp"];
+
+#[derive(Deserialize)]
+struct DrsObject {
+    #[serde(default)]
+    access_methods: Vec<AccessMethod>,
+    #[serde(default)]
+    contents: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct AccessMethod {
+    #[serde(rename = "type")]
+    access_type: String,
+    access_url: Option<AccessUrl>,
+    access_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AccessUrl {
+    url: String,
+    // given as "<name>: <value>"
+    #[serde(default)]
+    headers: Vec<String>,
+}
+
+impl Fetcher for Drs {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        let (access_url, headers) = resolve(url, client)?;
+        match access_url.scheme() {
+            "http" | "https" => get(client, &access_url, headers, offset),
+            _ => open_remote(&access_url, client, offset),
+        }
+    }
+
+    fn read_tail(&self, url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+        let (access_url, headers) = resolve(url, client)?;
+        match access_url.scheme() {
+            "http" | "https" => get_tail(client, &access_url, headers, length),
+            _ => read_remote_tail(&access_url, client, length),
+        }
+    }
+}
+
+// Resolve the DRS URL into the access URL of the object and the headers to request it with.
+fn resolve(url: &Url, client: &Client) -> Result<(Url, HeaderMap)> {
+    let object_id = url.path().trim_start_matches('/');
+    if object_id.is_empty() {
+        bail!("The DRS URL has no object ID: {}", url.as_str());
+    }
+    let objects_url = api_base_url(url)?.join("ga4gh/drs/v1/objects/")?;
+    let object_url = objects_url.join(object_id)?;
+    let api_headers = bearer_token_from_env("TATAKI_DRS_BEARER_TOKEN")?;
+
+    let object: DrsObject = get_json(client, &object_url, api_headers.clone())
+        .with_context(|| format!("Failed to resolve the DRS object {}", url.as_str()))?;
+    if object.access_methods.is_empty() && !object.contents.is_empty() {
+        bail!(
+            "The DRS object {} is a bundle of other objects, which is not supported. Give the objects in it instead.",
+            url.as_str()
+        );
+    }
+
+    let access_method = SUPPORTED_ACCESS_TYPES
+        .iter()
+        .find_map(|access_type| {
+            object
+                .access_methods
+                .iter()
+                .find(|method| method.access_type == *access_type)
+        })
+        .with_context(|| {
+            format!(
+                "The DRS object {} has no access method of the supported types: {}",
+                url.as_str(),
+                SUPPORTED_ACCESS_TYPES.join(", ")
+            )
+        })?;
+
+    // the access URL is either in the object, or obtained from the access endpoint with the access ID.
+    let obtained_access_url: AccessUrl;
+    let access_url = match (&access_method.access_url, &access_method.access_id) {
+        (Some(access_url), _) => access_url,
+        (None, Some(access_id)) => {
+            let access_endpoint =
+                objects_url.join(&format!("{}/access/{}", object_id, access_id))?;
+            obtained_access_url = get_json(client, &access_endpoint, api_headers)?;
+            &obtained_access_url
+        }
+        (None, None) => bail!(
+            "The access method of the DRS object {} has neither an access URL nor an access ID",
+            url.as_str()
+        ),
+    };
+    debug!("Resolved {} into {}", url.as_str(), access_url.url);
+
+    let resolved_url = Url::parse(&access_url.url)
+        .with_context(|| format!("Invalid access URL: {}", access_url.url))?;
+    Ok((resolved_url, parse_headers(&access_url.headers)?))
+}
+
+fn parse_headers(headers: &[String]) -> Result<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for header in headers {
+        let (name, value) = header
+            .split_once(':')
+            .with_context(|| format!("Invalid header of the access URL: {}", header))?;
+        header_map.insert(
+            HeaderName::from_bytes(name.trim().as_bytes())?,
+            HeaderValue::from_str(value.trim())?,
+        );
+    }
+    Ok(header_map)
+}
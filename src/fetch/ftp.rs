@@ -0,0 +1,319 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use url::Url;
+
+use crate::fetch::{skip, Client, FetchError, Fetcher, Remote};
+
+// Fetch `ftp://<host>/<path>` in the passive mode, such as the files served by ENA, NCBI and Ensembl.
+// The server is logged in to with the user and the password in the URL, or the ones given for the host by `--http-config`, and anonymously without them.
+pub struct Ftp;
+
+const DEFAULT_PORT: u16 = 21;
+
+impl Fetcher for Ftp {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        let (mut control, path) = login(url, client)?;
+        // most servers tell the size and the modification time, which are not required though.
+        let size = control
+            .query(&format!("SIZE {}", path))
+            .and_then(|size| size.parse::<u64>().ok());
+        let modified = control.query(&format!("MDTM {}", path));
+
+        let data = control.open_data_connection()?;
+        // start from the offset if the server supports it, and skip to the offset otherwise.
+        let restarted = offset > 0 && control.query(&format!("REST {}", offset)).is_some();
+        control.command(&format!("RETR {}", path), &[125, 150])?;
+
+        let remote = Remote {
+            reader: Box::new(Transfer {
+                data,
+                expiry: control.expiry,
+                control: Some(control),
+            }),
+            content_length: size
+                .map(|size| size.saturating_sub(if restarted { offset } else { 0 })),
+            validator: modified,
+        };
+        if offset > 0 && !restarted {
+            return skip(remote, offset);
+        }
+        Ok(remote)
+    }
+
+    fn read_tail(&self, url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+        let (mut control, path) = login(url, client)?;
+        // the end is found from the size, and retrieved with REST, both of which some servers do not support.
+        let Some(size) = control
+            .query(&format!("SIZE {}", path))
+            .and_then(|size| size.parse::<u64>().ok())
+        else {
+            return Ok(None);
+        };
+        let offset = size.saturating_sub(length);
+
+        let data = control.open_data_connection()?;
+        if offset > 0 && control.query(&format!("REST {}", offset)).is_none() {
+            return Ok(None);
+        }
+        control.command(&format!("RETR {}", path), &[125, 150])?;
+
+        let mut tail = Vec::new();
+        Transfer {
+            data,
+            expiry: control.expiry,
+            control: Some(control),
+        }
+        .read_to_end(&mut tail)?;
+        Ok(Some(tail))
+    }
+}
+
+// Log in to the server with the credentials for the URL, returning the control connection and the path of the file.
+// The request, from connecting to the server to receiving the whole file, must complete within the timeout of the client, as HTTP requests do.
+fn login(url: &Url, client: &Client) -> Result<(Control, String)> {
+    if client.has_proxy() {
+        bail!(
+            "FTP cannot be sent through the proxy given by `--proxy` or `--http-config`: {}",
+            url.as_str()
+        );
+    }
+    let expiry = Instant::now() + client.timeout();
+    let host = url
+        .host_str()
+        .with_context(|| format!("The FTP URL has no host: {}", url.as_str()))?;
+    // the path is relative to the directory logged in to, which is the root for anonymous users.
+    let path = decode(url.path().trim_start_matches('/'))?;
+    let (user, password) = match (url.username(), client.basic_auth(url)) {
+        ("", Some(basic_auth)) => (basic_auth.username.clone(), basic_auth.password.clone()),
+        ("", None) => ("anonymous".to_string(), "anonymous@".to_string()),
+        (user, _) => (
+            decode(user)?,
+            url.password()
+                .map(decode)
+                .transpose()?
+                .unwrap_or_else(|| "anonymous@".to_string()),
+        ),
+    };
+
+    let mut control = Control::connect(host, url.port().unwrap_or(DEFAULT_PORT), expiry)?;
+    if control.command(&format!("USER {}", user), &[230, 331])? == 331 {
+        control.command(&format!("PASS {}", password), &[230, 202])?;
+    }
+    control.command("TYPE I", &[200])?;
+    Ok((control, path))
+}
+
+fn decode(encoded: &str) -> Result<String> {
+    Ok(percent_encoding::percent_decode_str(encoded)
+        .decode_utf8()?
+        .into_owned())
+}
+
+fn connect(addrs: impl ToSocketAddrs, expiry: Instant) -> Result<TcpStream> {
+    let mut last_error = None;
+    for addr in addrs.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, remaining(expiry)?) {
+            Ok(stream) => {
+                set_timeouts(&stream, expiry)?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => Err(e.into()),
+        None => bail!("No address was found for the FTP server"),
+    }
+}
+
+// The time left until the request expires, which fails as timed out once it has expired.
+fn remaining(expiry: Instant) -> io::Result<Duration> {
+    let remaining = expiry.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(timed_out());
+    }
+    Ok(remaining)
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "The request to the FTP server timed out",
+    )
+}
+
+// A read or a write exceeding the timeout of the socket fails with `WouldBlock` on some platforms, which is reported as timed out instead.
+fn map_timeout(e: io::Error) -> io::Error {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => timed_out(),
+        _ => e,
+    }
+}
+
+// Wait for the server only until the request expires, both to read from it and to write to it.
+fn set_timeouts(stream: &TcpStream, expiry: Instant) -> io::Result<()> {
+    let remaining = remaining(expiry)?;
+    stream.set_read_timeout(Some(remaining))?;
+    stream.set_write_timeout(Some(remaining))
+}
+
+// The control connection, over which the commands are sent and the replies are received.
+struct Control {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    host: String,
+    expiry: Instant,
+}
+
+impl Control {
+    fn connect(host: &str, port: u16, expiry: Instant) -> Result<Self> {
+        let stream = connect((host, port), expiry)?;
+        let mut control = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            host: host.to_string(),
+            expiry,
+        };
+        control.expect_reply("the connection", &[220])?;
+        Ok(control)
+    }
+
+    // Send the command and return the code of the reply, which must be one of the expected codes.
+    fn command(&mut self, command: &str, expected: &[u16]) -> Result<u16> {
+        Ok(self.command_with_text(command, expected)?.0)
+    }
+
+    // Send the command and return the text of the reply if it has succeeded, for the commands that the server may not support.
+    fn query(&mut self, command: &str) -> Option<String> {
+        let (_, text) = self.command_with_text(command, &[213, 350]).ok()?;
+        Some(text.trim().to_string())
+    }
+
+    fn command_with_text(&mut self, command: &str, expected: &[u16]) -> Result<(u16, String)> {
+        let name = command.split(' ').next().unwrap_or_default();
+        debug!(
+            "Sending {} to the FTP server {}",
+            if name == "PASS" { name } else { command },
+            self.host
+        );
+        set_timeouts(&self.writer, self.expiry)?;
+        self.writer
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .map_err(map_timeout)?;
+        self.expect_reply(name, expected)
+    }
+
+    fn expect_reply(&mut self, replied_to: &str, expected: &[u16]) -> Result<(u16, String)> {
+        let (code, text) = self.reply()?;
+        if !expected.contains(&code) {
+            // the replies in 4xx tell that the failure is temporary.
+            bail!(FetchError::new(
+                format!(
+                    "The FTP server {} replied to {} with {} {}",
+                    self.host,
+                    replied_to,
+                    code,
+                    text.trim()
+                ),
+                (400..500).contains(&code)
+            ));
+        }
+        Ok((code, text))
+    }
+
+    // Receive a reply, which spans multiple lines from "<code>-" to "<code> " if the fourth character is "-".
+    fn reply(&mut self) -> Result<(u16, String)> {
+        let mut line = self.read_line()?;
+        let code: u16 = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .with_context(|| {
+                format!("Invalid reply from the FTP server {}: {}", self.host, line)
+            })?;
+        let mut text = line.get(4..).unwrap_or_default().to_string();
+        if line.as_bytes().get(3) == Some(&b'-') {
+            let last_line_prefix = format!("{} ", code);
+            while !line.starts_with(&last_line_prefix) {
+                line = self.read_line()?;
+                text.push('\n');
+                text.push_str(line.strip_prefix(&last_line_prefix).unwrap_or(&line));
+            }
+        }
+        Ok((code, text))
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(map_timeout)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("The FTP server {} closed the connection", self.host),
+            )
+            .into());
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    // Open the data connection in the passive mode, asking for the port with EPSV, or with PASV for the servers not supporting it.
+    fn open_data_connection(&mut self) -> Result<TcpStream> {
+        let port = match self.command_with_text("EPSV", &[229]) {
+            // "Entering Extended Passive Mode (|||<port>|)"
+            Ok((_, text)) => text.split('|').nth(3).and_then(|port| port.parse().ok()),
+            Err(_) => {
+                // "Entering Passive Mode (h1,h2,h3,h4,p1,p2)"
+                let (_, text) = self.command_with_text("PASV", &[227])?;
+                let numbers = text
+                    .split(|c: char| !c.is_ascii_digit())
+                    .filter_map(|number| number.parse::<u16>().ok())
+                    .collect::<Vec<_>>();
+                match numbers[..] {
+                    [.., p1, p2] if numbers.len() >= 6 && p1 < 256 && p2 < 256 => {
+                        Some(p1 * 256 + p2)
+                    }
+                    _ => None,
+                }
+            }
+        }
+        .with_context(|| {
+            format!(
+                "Invalid reply to the passive mode from the FTP server {}",
+                self.host
+            )
+        })?;
+        // the address told by PASV is ignored, as it is often a private one behind NAT.
+        connect(
+            SocketAddr::new(self.writer.peer_addr()?.ip(), port),
+            self.expiry,
+        )
+    }
+}
+
+// The data connection of a file being retrieved. The reply to the transfer is checked at the end, so that a transfer aborted by the server is not taken for the whole file.
+struct Transfer {
+    data: TcpStream,
+    expiry: Instant,
+    control: Option<Control>,
+}
+
+impl Read for Transfer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.set_read_timeout(Some(remaining(self.expiry)?))?;
+        let bytes_read = self.data.read(buf).map_err(map_timeout)?;
+        if bytes_read == 0 && !buf.is_empty() {
+            if let Some(mut control) = self.control.take() {
+                set_timeouts(&control.writer, self.expiry)?;
+                // keep the `FetchError` as is to tell whether the failure is temporary.
+                control.expect_reply("RETR", &[226, 250]).map_err(|e| {
+                    match e.downcast::<FetchError>() {
+                        Ok(e) => io::Error::other(e),
+                        Err(e) => io::Error::other(e),
+                    }
+                })?;
+            }
+        }
+        Ok(bytes_read)
+    }
+}
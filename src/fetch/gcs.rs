@@ -0,0 +1,198 @@
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use url::Url;
+
+use crate::fetch::http::{bearer_token_from_env, get, get_tail};
+use crate::fetch::{Client, Fetcher, Remote};
+
+// Fetch `gs://<bucket>/<object>` from Google Cloud Storage, or from the emulator given by `STORAGE_EMULATOR_HOST`.
+// The access token is taken from `GOOGLE_OAUTH_ACCESS_TOKEN`, or obtained with the service account key or the user credentials in the file given by `GOOGLE_APPLICATION_CREDENTIALS`.
+// The object is read anonymously without them.
+pub struct Gcs;
+
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+
+const READ_ONLY_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+
+// Characters left unencoded in the object names in the paths of the JSON API. "/" is encoded as the object name is a single segment.
+const OBJECT_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// The file given by `GOOGLE_APPLICATION_CREDENTIALS`, either a service account key or the user credentials created by `gcloud auth application-default login`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CredentialsFile {
+    ServiceAccount {
+        client_email: String,
+        private_key: String,
+        token_uri: String,
+    },
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+        #[serde(default = "default_token_uri")]
+        token_uri: String,
+    },
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+impl Fetcher for Gcs {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        let (object_url, headers) = object_request(url, client)?;
+        get(client, &object_url, headers, offset)
+    }
+
+    fn read_tail(&self, url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+        let (object_url, headers) = object_request(url, client)?;
+        get_tail(client, &object_url, headers, length)
+    }
+}
+
+// The URL of the object and the headers to request it with.
+fn object_request(url: &Url, client: &Client) -> Result<(Url, HeaderMap)> {
+    let bucket = url
+        .host_str()
+        .with_context(|| format!("The GCS URL has no bucket: {}", url.as_str()))?;
+    let object =
+        percent_encoding::percent_decode_str(url.path().trim_start_matches('/')).decode_utf8()?;
+
+    let endpoint = match std::env::var("STORAGE_EMULATOR_HOST") {
+        Ok(host) if host.contains("://") => host,
+        Ok(host) => format!("http://{}", host),
+        Err(_) => DEFAULT_ENDPOINT.to_string(),
+    };
+    let object_url = Url::parse(&format!(
+        "{}/storage/v1/b/{}/o/{}?alt=media",
+        endpoint.trim_end_matches('/'),
+        bucket,
+        percent_encoding::utf8_percent_encode(&object, OBJECT_ENCODE_SET)
+    ))
+    .with_context(|| format!("Invalid GCS endpoint: {}", endpoint))?;
+
+    Ok((object_url, authorization(client)?))
+}
+
+fn authorization(client: &Client) -> Result<HeaderMap> {
+    let headers = bearer_token_from_env("GOOGLE_OAUTH_ACCESS_TOKEN")?;
+    if !headers.is_empty() {
+        return Ok(headers);
+    }
+
+    let Some(path) = std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") else {
+        return Ok(headers);
+    };
+    let content = std::fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Failed to read the credentials {} given by GOOGLE_APPLICATION_CREDENTIALS",
+            path.to_string_lossy()
+        )
+    })?;
+    let credentials: CredentialsFile = serde_json::from_str(&content).with_context(|| {
+        format!(
+            "Unsupported credentials {} given by GOOGLE_APPLICATION_CREDENTIALS. Only service account keys and user credentials are supported.",
+            path.to_string_lossy()
+        )
+    })?;
+
+    let token_url = |token_uri: &str| {
+        Url::parse(token_uri).with_context(|| format!("Invalid token URI: {}", token_uri))
+    };
+    let response = match &credentials {
+        CredentialsFile::ServiceAccount {
+            client_email,
+            private_key,
+            token_uri,
+        } => client
+            .post(&token_url(token_uri)?)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                (
+                    "assertion",
+                    &signed_jwt(client_email, private_key, token_uri)?,
+                ),
+            ])
+            .send()?,
+        CredentialsFile::AuthorizedUser {
+            client_id,
+            client_secret,
+            refresh_token,
+            token_uri,
+        } => client
+            .post(&token_url(token_uri)?)
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("refresh_token", refresh_token),
+            ])
+            .send()?,
+    };
+    if !response.status().is_success() {
+        bail!(
+            "Failed to obtain the access token for GCS with status code {}",
+            response.status()
+        );
+    }
+    let token: TokenResponse =
+        serde_json::from_reader(response).context("Failed to parse the access token for GCS")?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        format!("Bearer {}", token.access_token).parse()?,
+    );
+    Ok(headers)
+}
+
+// A JSON Web Token signed with the key of the service account, which is exchanged for an access token.
+fn signed_jwt(client_email: &str, private_key: &str, token_uri: &str) -> Result<String> {
+    let issued_at = Utc::now().timestamp();
+    let header = serde_json::json!({"alg": "RS256", "typ": "JWT"});
+    let claims = serde_json::json!({
+        "iss": client_email,
+        "scope": READ_ONLY_SCOPE,
+        "aud": token_uri,
+        "iat": issued_at,
+        "exp": issued_at + 3600,
+    });
+    let unsigned = format!(
+        "{}.{}",
+        base64_url(header.to_string().as_bytes()),
+        base64_url(claims.to_string().as_bytes())
+    );
+
+    let key = PKey::private_key_from_pem(private_key.as_bytes())
+        .context("Failed to read the private key of the service account")?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(unsigned.as_bytes())?;
+    Ok(format!(
+        "{}.{}",
+        unsigned,
+        base64_url(&signer.sign_to_vec()?)
+    ))
+}
+
+// base64url without padding, as used in JSON Web Tokens.
+fn base64_url(bytes: &[u8]) -> String {
+    openssl::base64::encode_block(bytes)
+        .trim_end_matches('=')
+        .replace('+', "-")
+        .replace('/', "_")
+}
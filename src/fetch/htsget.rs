@@ -0,0 +1,124 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Cursor, Read};
+use url::Url;
+
+use crate::fetch::http::{api_base_url, bearer_token_from_env, get, get_json};
+use crate::fetch::{skip, Client, Fetcher, Remote};
+
+// Fetch `htsget://<host>/<path>`, such as `htsget://example.org/reads/NA12878`, with a GA4GH htsget ticket obtained from `https://<host>/<path>`.
+// The query of the URL is passed to the ticket request as is, such as `?format=CRAM` or `?referenceName=chr1`.
+// The ticket is requested with the bearer token in `TATAKI_HTSGET_BEARER_TOKEN` if it is set.
+pub struct Htsget;
+
+#[derive(Deserialize)]
+struct TicketResponse {
+    htsget: Ticket,
+}
+
+#[derive(Deserialize)]
+struct Ticket {
+    format: Option<String>,
+    urls: Vec<Block>,
+}
+
+// A part of the content, which is either downloaded from the URL with the headers, or embedded in the `data:` URI.
+#[derive(Deserialize)]
+struct Block {
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+impl Fetcher for Htsget {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        let mut ticket_url = api_base_url(url)?.join(url.path().trim_start_matches('/'))?;
+        ticket_url.set_query(url.query());
+
+        let ticket: TicketResponse = get_json(
+            client,
+            &ticket_url,
+            bearer_token_from_env("TATAKI_HTSGET_BEARER_TOKEN")?,
+        )
+        .with_context(|| format!("Failed to obtain the htsget ticket for {}", url.as_str()))?;
+        debug!(
+            "htsget ticket for {}: {} blocks in {} format",
+            url.as_str(),
+            ticket.htsget.urls.len(),
+            ticket.htsget.format.as_deref().unwrap_or("BAM")
+        );
+
+        // the blocks are not addressed by the offset in the whole content.
+        skip(
+            Remote {
+                reader: Box::new(Blocks {
+                    client: client.clone(),
+                    blocks: ticket.htsget.urls.into(),
+                    current: None,
+                }),
+                content_length: None,
+                validator: None,
+            },
+            offset,
+        )
+    }
+}
+
+// The concatenation of the blocks of the ticket. Each block is requested once the preceding ones have been read, so that the rest is not downloaded if the reading stops.
+struct Blocks {
+    client: Client,
+    blocks: VecDeque<Block>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl Blocks {
+    fn open(&self, block: &Block) -> Result<Box<dyn Read>> {
+        if let Some(data) = block.url.strip_prefix("data:") {
+            return Ok(Box::new(Cursor::new(decode_data_uri(data)?)));
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &block.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        let url = Url::parse(&block.url)
+            .with_context(|| format!("Invalid URL in the htsget ticket: {}", block.url))?;
+        Ok(get(&self.client, &url, headers, 0)?.reader)
+    }
+}
+
+impl Read for Blocks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(current) = self.current.as_mut() {
+                let bytes_read = current.read(buf)?;
+                if bytes_read > 0 || buf.is_empty() {
+                    return Ok(bytes_read);
+                }
+            }
+            let Some(block) = self.blocks.pop_front() else {
+                return Ok(0);
+            };
+            self.current = Some(self.open(&block).map_err(io::Error::other)?);
+        }
+    }
+}
+
+// decode `<media type>[;base64],<data>` following `data:`.
+fn decode_data_uri(data: &str) -> Result<Vec<u8>> {
+    let Some((media_type, encoded)) = data.split_once(',') else {
+        bail!("Invalid data URI in the htsget ticket");
+    };
+    if media_type.ends_with(";base64") {
+        Ok(openssl::base64::decode_block(encoded)
+            .context("Invalid base64 in the data URI of the htsget ticket")?)
+    } else {
+        Ok(percent_encoding::percent_decode_str(encoded).collect())
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED, RANGE};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::io::Read;
+use std::net::IpAddr;
+use url::Url;
+
+use crate::fetch::{skip, Client, FetchError, Fetcher, Remote};
+
+pub struct Http;
+
+impl Fetcher for Http {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        get(client, url, HeaderMap::new(), offset)
+    }
+
+    fn read_tail(&self, url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+        get_tail(client, url, HeaderMap::new(), length)
+    }
+}
+
+// Send a GET request for the content from `offset`, and return the body of the response if it has succeeded.
+pub fn get(client: &Client, url: &Url, headers: HeaderMap, offset: u64) -> Result<Remote> {
+    let mut request = client.get(url).headers(headers);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+    let response = request.send()?;
+    let status = response.status();
+
+    if !status.is_success() {
+        return Err(status_error(url, status).into());
+    }
+
+    let validator = [ETAG, LAST_MODIFIED]
+        .iter()
+        .find_map(|name| response.headers().get(name)?.to_str().ok())
+        .map(str::to_string);
+    let remote = Remote {
+        content_length: response.content_length(),
+        validator,
+        reader: Box::new(response),
+    };
+    // the server ignoring the range sends the whole content.
+    if offset > 0 && status != StatusCode::PARTIAL_CONTENT {
+        return skip(remote, offset);
+    }
+    Ok(remote)
+}
+
+// Send a GET request for the last `length` bytes of the content, and return them if the server has served the range rather than the whole content.
+pub fn get_tail(
+    client: &Client,
+    url: &Url,
+    headers: HeaderMap,
+    length: u64,
+) -> Result<Option<Vec<u8>>> {
+    let response = client
+        .get(url)
+        .headers(headers)
+        .header(RANGE, format!("bytes=-{}", length))
+        .send()?;
+    let status = response.status();
+    // an empty content has no range to serve.
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(Some(Vec::new()));
+    }
+    if !status.is_success() {
+        return Err(status_error(url, status).into());
+    }
+    if status != StatusCode::PARTIAL_CONTENT {
+        return Ok(None);
+    }
+    let mut tail = Vec::new();
+    response.take(length).read_to_end(&mut tail)?;
+    Ok(Some(tail))
+}
+
+fn status_error(url: &Url, status: StatusCode) -> FetchError {
+    // the server errors and rate limiting are expected to be resolved by retrying.
+    let transient = status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT;
+    FetchError::new(
+        format!(
+            "Failed to download from {} with status code {}",
+            url.as_str(),
+            status
+        ),
+        transient,
+    )
+}
+
+// Send a GET request to an API, and parse the response as JSON.
+pub fn get_json<T: DeserializeOwned>(client: &Client, url: &Url, headers: HeaderMap) -> Result<T> {
+    let remote = get(client, url, headers, 0)?;
+    serde_json::from_reader(remote.reader)
+        .with_context(|| format!("Failed to parse the response from {}", url.as_str()))
+}
+
+// The base URL of the API served at the host of `url`, such as `drs://<host>/<id>`.
+// The API is reached over HTTPS, except for the loopback addresses, which are reached over HTTP to be served locally without certificates.
+pub fn api_base_url(url: &Url) -> Result<Url> {
+    let host = url
+        .host_str()
+        .with_context(|| format!("The URL has no host: {}", url.as_str()))?;
+    let is_loopback = host == "localhost"
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback());
+    let scheme = if is_loopback { "http" } else { "https" };
+
+    let authority = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    Ok(Url::parse(&format!("{}://{}/", scheme, authority))?)
+}
+
+// The headers with the bearer token taken from the environment variable, if it is set.
+pub fn bearer_token_from_env(name: &str) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = std::env::var(name).ok().filter(|token| !token.is_empty()) {
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", token)
+                .parse()
+                .with_context(|| format!("Invalid token in {}", name))?,
+        );
+    }
+    Ok(headers)
+}
@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::header::HeaderMap;
+use url::Url;
+
+use crate::cache::to_hex;
+use crate::fetch::http::{get, get_tail};
+use crate::fetch::{Client, Fetcher, Remote};
+
+// Fetch `s3://<bucket>/<key>` from Amazon S3 or an S3-compatible endpoint such as MinIO, given by `AWS_ENDPOINT_URL_S3` or `AWS_ENDPOINT_URL`.
+// The request is signed with Signature Version 4 if `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY` are set, and is anonymous otherwise.
+pub struct S3;
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+// The payload of a GET request is empty, and S3 accepts it without being hashed.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+// Characters left unencoded in the paths of the requests, as required by Signature Version 4.
+const PATH_ENCODE_SET: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Some(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+impl Fetcher for S3 {
+    fn open(&self, url: &Url, client: &Client, offset: u64) -> Result<Remote> {
+        let (object_url, headers) = object_request(url)?;
+        get(client, &object_url, headers, offset)
+    }
+
+    fn read_tail(&self, url: &Url, client: &Client, length: u64) -> Result<Option<Vec<u8>>> {
+        let (object_url, headers) = object_request(url)?;
+        get_tail(client, &object_url, headers, length)
+    }
+}
+
+// The URL of the object and the headers to request it with.
+fn object_request(url: &Url) -> Result<(Url, HeaderMap)> {
+    let bucket = url
+        .host_str()
+        .with_context(|| format!("The S3 URL has no bucket: {}", url.as_str()))?;
+    let key =
+        percent_encoding::percent_decode_str(url.path().trim_start_matches('/')).decode_utf8()?;
+    let region = std::env::var("AWS_REGION")
+        .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+        .unwrap_or_else(|_| DEFAULT_REGION.to_string());
+
+    // a custom endpoint is addressed with the bucket in the path, which S3-compatible services support.
+    let path = format!(
+        "/{}",
+        percent_encoding::utf8_percent_encode(&key, PATH_ENCODE_SET)
+    );
+    let object_url =
+        match std::env::var("AWS_ENDPOINT_URL_S3").or_else(|_| std::env::var("AWS_ENDPOINT_URL")) {
+            Ok(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                Url::parse(&format!("{}/{}{}", endpoint, bucket, path))
+                    .with_context(|| format!("Invalid S3 endpoint: {}", endpoint))?
+            }
+            Err(_) => Url::parse(&format!(
+                "https://{}.s3.{}.amazonaws.com{}",
+                bucket, region, path
+            ))?,
+        };
+
+    let headers = match Credentials::from_env() {
+        Some(credentials) => sign(&object_url, &region, &credentials)?,
+        None => HeaderMap::new(),
+    };
+    Ok((object_url, headers))
+}
+
+// The headers of a GET request to `url` signed with Signature Version 4.
+fn sign(url: &Url, region: &str, credentials: &Credentials) -> Result<HeaderMap> {
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    // the headers to sign, sorted by their names.
+    let mut signed_headers = vec![
+        ("host", host),
+        ("x-amz-content-sha256", UNSIGNED_PAYLOAD.to_string()),
+        ("x-amz-date", amz_date.clone()),
+    ];
+    if let Some(session_token) = &credentials.session_token {
+        signed_headers.push(("x-amz-security-token", session_token.clone()));
+    }
+    let signed_header_names = signed_headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let canonical_request = format!(
+        "GET\n{}\n{}\n{}\n{}\n{}",
+        url.path(),
+        url.query().unwrap_or_default(),
+        signed_headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect::<String>(),
+        signed_header_names,
+        UNSIGNED_PAYLOAD
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        to_hex(&hash(
+            MessageDigest::sha256(),
+            canonical_request.as_bytes()
+        )?)
+    );
+
+    let mut signing_key = format!("AWS4{}", credentials.secret_access_key).into_bytes();
+    for part in [date.as_str(), region, "s3", "aws4_request"] {
+        signing_key = hmac_sha256(&signing_key, part.as_bytes())?;
+    }
+    let signature = to_hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes())?);
+
+    let mut headers = HeaderMap::new();
+    // `host` is set by the client from the URL.
+    for (name, value) in signed_headers.iter().skip(1) {
+        headers.insert(*name, value.parse()?);
+    }
+    headers.insert(
+        reqwest::header::AUTHORIZATION,
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_header_names, signature
+        )
+        .parse()?,
+    );
+    Ok(headers)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
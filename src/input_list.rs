@@ -0,0 +1,105 @@
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::io::Read;
+
+use crate::args::Expectation;
+
+// Name of the column holding the inputs in a CSV/TSV list
+const PATH_COLUMN: &str = "path";
+// Name of the optional column holding the expected format of each input, the same as `--expect`
+const EXPECT_COLUMN: &str = "expect";
+
+// Inputs and their expected formats read from the list given by `--from-file`.
+#[derive(Debug, Default)]
+pub struct InputList {
+    pub inputs: Vec<String>,
+    pub expectations: Vec<Expectation>,
+}
+
+// Read the list of inputs from a file, or from STDIN if `path` is "-".
+// A CSV or TSV whose header has a `path` column is read as a table, and otherwise each line is an input.
+// Empty lines and lines starting with "#" are skipped in both cases.
+pub fn read_input_list(path: &str) -> Result<InputList> {
+    let content = if path == "-" {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("Failed to read the list of inputs from STDIN")?;
+        content
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the list of inputs from {}", path))?
+    };
+
+    let lines: Vec<&str> = content
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .collect();
+
+    let input_list = match lines.first().and_then(|header| table_delimiter(header)) {
+        Some(delimiter) => parse_table(&lines, delimiter)
+            .with_context(|| format!("Failed to parse the list of inputs {}", path))?,
+        None => InputList {
+            inputs: lines.iter().map(|line| line.trim().to_string()).collect(),
+            expectations: Vec::new(),
+        },
+    };
+
+    debug!("Inputs read from {}: {:?}", path, input_list);
+    Ok(input_list)
+}
+
+// The delimiter of the table if the header has the path column, either as TSV or CSV.
+fn table_delimiter(header: &str) -> Option<u8> {
+    [b'\t', b',']
+        .into_iter()
+        .find(|&delimiter| column_index(header, delimiter, PATH_COLUMN).is_some())
+}
+
+fn column_index(header: &str, delimiter: u8, name: &str) -> Option<usize> {
+    header
+        .split(char::from(delimiter))
+        .position(|column| column.trim().trim_matches('"').eq_ignore_ascii_case(name))
+}
+
+fn parse_table(lines: &[&str], delimiter: u8) -> Result<InputList> {
+    let content = lines.join("\n");
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let headers = reader.headers()?.clone();
+    let find = |name: &str| {
+        headers
+            .iter()
+            .position(|column| column.trim().eq_ignore_ascii_case(name))
+    };
+    let path_index = find(PATH_COLUMN).context("The header has no `path` column")?;
+    let expect_index = find(EXPECT_COLUMN);
+
+    let mut input_list = InputList::default();
+    for (count, record) in reader.records().enumerate() {
+        let record = record?;
+
+        let input = match record.get(path_index).map(str::trim) {
+            Some(input) if !input.is_empty() => input.to_string(),
+            _ => bail!("The `path` column is empty in row {}", count + 1),
+        };
+
+        if let Some(format) = expect_index
+            .and_then(|index| record.get(index))
+            .map(str::trim)
+            .filter(|format| !format.is_empty())
+        {
+            input_list.expectations.push(Expectation {
+                input: Some(input.clone()),
+                format: format.to_string(),
+            });
+        }
+        input_list.inputs.push(input);
+    }
+
+    Ok(input_list)
+}
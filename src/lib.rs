@@ -1,9 +1,14 @@
+mod archive;
 pub mod args;
-mod buffered_read_seek;
+mod cache;
+mod discover;
 mod edam;
 mod ext_tools;
 mod fetch;
+mod input_list;
+mod limits;
 mod logger;
 pub mod module;
 mod parser;
 mod source;
+mod truncation;
@@ -0,0 +1,219 @@
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::args::Args;
+use crate::module::InvokeOptions;
+
+/// Decompressed size from which the compression ratio is checked, so that small inputs with a high ratio, such as a file of repeated lines, are not rejected.
+const MIN_SIZE_TO_CHECK_RATIO: u64 = 1024 * 1024;
+
+// Limits on the resources used for a single input, given by `--max-decompressed-size`, `--max-compression-ratio`, `--max-download-size` and `--input-timeout`.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Maximum number of bytes to decompress from an input
+    pub max_decompressed_size: Option<u64>,
+    /// Maximum ratio of the decompressed size to the compressed size
+    pub max_compression_ratio: Option<f64>,
+    /// Maximum number of bytes to download for an input
+    pub max_download_size: Option<u64>,
+    /// Maximum time to process an input
+    pub input_timeout: Option<Duration>,
+}
+
+impl From<&Args> for Limits {
+    fn from(args: &Args) -> Self {
+        Self {
+            max_decompressed_size: args.max_decompressed_size,
+            max_compression_ratio: args.max_compression_ratio,
+            max_download_size: args.max_download_size,
+            input_timeout: args.input_timeout.map(Duration::from_secs),
+        }
+    }
+}
+
+// The error returned when an input exceeds one of the limits. The input is reported with the "limit_exceeded" status instead of failing the whole run.
+#[derive(Debug)]
+pub struct LimitExceeded(String);
+
+impl LimitExceeded {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+
+    pub fn message(&self) -> &str {
+        &self.0
+    }
+
+    // Readers can only return `io::Error`, so wrap it to be found by `find_limit_exceeded` later.
+    pub fn into_io_error(self) -> io::Error {
+        io::Error::other(self)
+    }
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+// Find the `LimitExceeded` in the chain of the error, including the ones wrapped in `io::Error` by readers.
+pub fn find_limit_exceeded(error: &anyhow::Error) -> Option<&LimitExceeded> {
+    error.chain().find_map(|cause| {
+        cause.downcast_ref::<LimitExceeded>().or_else(|| {
+            cause
+                .downcast_ref::<io::Error>()
+                .and_then(io::Error::get_ref)
+                .and_then(|inner| inner.downcast_ref::<LimitExceeded>())
+        })
+    })
+}
+
+// The time by which an input must have been processed.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Instant,
+    timeout: Duration,
+}
+
+impl Deadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+            timeout,
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    pub fn check(&self) -> Result<(), LimitExceeded> {
+        if Instant::now() > self.at {
+            Err(LimitExceeded::new(format!(
+                "The input has not been processed within {} seconds given by `--input-timeout`",
+                self.timeout.as_secs()
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// A reader which fails once the deadline of the input has passed. Used for reads which do not decompress, such as parsers reading files.
+pub struct DeadlineReader<R> {
+    inner: R,
+    deadline: Option<Deadline>,
+}
+
+impl<R> DeadlineReader<R> {
+    pub const fn new(inner: R, deadline: Option<Deadline>) -> Self {
+        Self { inner, deadline }
+    }
+}
+
+impl<R: Read> Read for DeadlineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(deadline) = &self.deadline {
+            deadline.check().map_err(LimitExceeded::into_io_error)?;
+        }
+        self.inner.read(buf)
+    }
+}
+
+// Counters of the compressed bytes read and the decompressed bytes produced for an input, checked against the limits.
+#[derive(Clone)]
+pub struct Guard {
+    compressed: Rc<Cell<u64>>,
+    decompressed: Rc<Cell<u64>>,
+    max_decompressed_size: Option<u64>,
+    max_compression_ratio: Option<f64>,
+    deadline: Option<Deadline>,
+}
+
+impl Guard {
+    pub fn new(options: &InvokeOptions) -> Self {
+        Self {
+            compressed: Rc::default(),
+            decompressed: Rc::default(),
+            max_decompressed_size: options.limits.max_decompressed_size,
+            max_compression_ratio: options.limits.max_compression_ratio,
+            deadline: options.deadline,
+        }
+    }
+
+    // Count the bytes read from `reader` as the compressed size.
+    pub fn count_compressed<R: Read>(&self, reader: R) -> GuardedReader<R> {
+        GuardedReader {
+            inner: reader,
+            guard: self.clone(),
+            decompressed: false,
+        }
+    }
+
+    // Add the compressed size known beforehand, such as the one of a file in a ZIP archive.
+    pub fn add_compressed(&self, size: u64) {
+        self.compressed.set(self.compressed.get() + size);
+    }
+
+    // Count the bytes read from `reader` as the decompressed size, and fail once it exceeds the limits.
+    pub fn check_decompressed<R: Read>(&self, reader: R) -> GuardedReader<R> {
+        GuardedReader {
+            inner: reader,
+            guard: self.clone(),
+            decompressed: true,
+        }
+    }
+
+    fn check(&self) -> Result<(), LimitExceeded> {
+        if let Some(deadline) = &self.deadline {
+            deadline.check()?;
+        }
+
+        let decompressed = self.decompressed.get();
+        if let Some(max) = self.max_decompressed_size {
+            if decompressed > max {
+                return Err(LimitExceeded::new(format!(
+                    "The decompressed size exceeds {} bytes given by `--max-decompressed-size`",
+                    max
+                )));
+            }
+        }
+        if let Some(max) = self.max_compression_ratio {
+            let compressed = self.compressed.get();
+            if decompressed >= MIN_SIZE_TO_CHECK_RATIO
+                && compressed > 0
+                && decompressed as f64 / compressed as f64 > max
+            {
+                return Err(LimitExceeded::new(format!(
+                    "The compression ratio exceeds {} given by `--max-compression-ratio` ({} bytes decompressed from {} bytes)",
+                    max, decompressed, compressed
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct GuardedReader<R> {
+    inner: R,
+    guard: Guard,
+    decompressed: bool,
+}
+
+impl<R: Read> Read for GuardedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        let counter = if self.decompressed {
+            &self.guard.decompressed
+        } else {
+            &self.guard.compressed
+        };
+        counter.set(counter.get() + bytes_read as u64);
+        self.guard.check().map_err(LimitExceeded::into_io_error)?;
+        Ok(bytes_read)
+    }
+}
@@ -2,8 +2,9 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use std::fs::File;
 use std::io::BufReader;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     // parse arguments and options with clap
     let args = tataki::args::Args::parse();
 
@@ -31,9 +32,8 @@ fn main() -> Result<()> {
 
     if args.dry_run {
         tataki::module::dry_run(config)?;
+        Ok(ExitCode::SUCCESS)
     } else {
-        tataki::module::run(config, args)?;
+        tataki::module::run(config, args)
     }
-
-    Ok(())
 }
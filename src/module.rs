@@ -1,40 +1,129 @@
 use anyhow::{anyhow, bail, Result};
-use log::{debug, info, warn};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use log::{debug, error, info, warn};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
 use tempfile::{NamedTempFile, TempDir};
 use url::Url;
 
-use crate::args::{Args, OutputFormat};
+use crate::archive::{detect_archive, detect_archive_in_header, for_each_member, ArchiveFormat};
+use crate::args::{Args, Command, Expectation, OutputFormat};
+use crate::cache::Cache;
+use crate::discover::{discover_inputs, DiscoverOptions, Input};
 use crate::ext_tools::{create_dummy_docker_executable, ensure_docker_presence};
-use crate::source::{CompressedFormat, Source};
+use crate::fetch::{Download, FetchOptions};
+use crate::input_list::read_input_list;
+use crate::limits::{find_limit_exceeded, Deadline, Guard, LimitExceeded, Limits};
+use crate::parser::Location;
+use crate::source::{peek, CompressedFormat, Source};
+use crate::truncation;
 
 // Struct to store the result of Parser invocation and ExtTools invocation.
-#[derive(Debug)]
+// It is serialized as is only to be cached, and is output through `ResultEntry`.
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ModuleResult {
     input: String,
     is_ok: bool,
     label: Option<String>,
     id: Option<String>,
+    // version of the format, such as `4.2` of VCF, if the parser tells it
+    version: Option<String>,
+    // properties of the format read by the parser, such as the sort order of SAM, keyed by their names
+    #[serde(default)]
+    details: BTreeMap<String, Detail>,
     error_message: Option<String>,
     decompressed: Option<DecompressedFormat>,
+    candidates: Option<Vec<Candidate>>,
+    ambiguous: bool,
+    evidence: Option<Evidence>,
+    confidence: Option<f64>,
+    validation: Option<Validation>,
+    error_location: Option<Location>,
+    errors: Vec<ModuleError>,
+    // results of the files in the input, if the input is an archive
+    members: Option<Vec<ModuleResult>>,
+    // compression formats of the input outermost first, if it has been compressed more than once
+    compression_layers: Vec<CompressionLayer>,
+    status: InputStatus,
+}
+
+// Whether the input has been processed within the limits given by `--max-decompressed-size` and the like, and whether it is complete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputStatus {
+    #[default]
+    Ok,
+    LimitExceeded,
+    // the BGZF file lacks the EOF marker or ends with a broken block, while its format may still have been detected from the beginning.
+    Truncated,
 }
 
 impl From<&CompressedFormat> for ModuleResult {
     fn from(compressed_format: &CompressedFormat) -> Self {
         match compressed_format {
             CompressedFormat::Bgzf => Self::with_result(None, None),
+            CompressedFormat::BgzfText => Self::with_result(
+                Some("bgzip".to_string()),
+                Some("http://edamontology.org/format_3615".to_string()),
+            ),
             CompressedFormat::GZ => Self::with_result(
                 Some("GZIP format".to_string()),
                 Some("http://edamontology.org/format_3989".to_string()),
             ),
-            CompressedFormat::BZ2 => Self::with_result(None, None),
+            CompressedFormat::Zstd => Self::with_result(
+                Some("Zstandard format".to_string()),
+                Some("http://edamontology.org/format_4006".to_string()),
+            ),
+            // EDAM has no format for bzip2, XZ, LZMA and LZ4.
+            CompressedFormat::BZ2
+            | CompressedFormat::Xz
+            | CompressedFormat::Lzma
+            | CompressedFormat::Lz4 => Self::with_result(None, None),
             CompressedFormat::None => Self::with_result(None, None),
         }
     }
 }
 
+// A compression format which the input has been compressed in, reported when the input has been compressed more than once.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompressionLayer {
+    format: String,
+    label: Option<String>,
+    id: Option<String>,
+}
+
+impl From<&CompressedFormat> for CompressionLayer {
+    fn from(compressed_format: &CompressedFormat) -> Self {
+        let edam = ModuleResult::from(compressed_format);
+        Self {
+            format: compressed_format.name().to_string(),
+            label: edam.label,
+            id: edam.id,
+        }
+    }
+}
+
+impl From<&ArchiveFormat> for ModuleResult {
+    fn from(archive_format: &ArchiveFormat) -> Self {
+        match archive_format {
+            ArchiveFormat::Zip => Self::with_result(
+                Some("ZIP format".to_string()),
+                Some("http://edamontology.org/format_3987".to_string()),
+            ),
+            ArchiveFormat::Tar => Self::with_result(
+                Some("TAR format".to_string()),
+                Some("http://edamontology.org/format_3981".to_string()),
+            ),
+        }
+    }
+}
+
 impl ModuleResult {
     pub const fn with_result(label: Option<String>, id: Option<String>) -> Self {
         Self {
@@ -42,11 +131,71 @@ impl ModuleResult {
             is_ok: true,
             label,
             id,
+            version: None,
+            details: BTreeMap::new(),
             error_message: None,
             decompressed: None,
+            candidates: None,
+            ambiguous: false,
+            evidence: None,
+            confidence: None,
+            validation: None,
+            error_location: None,
+            errors: Vec::new(),
+            members: None,
+            compression_layers: Vec::new(),
+            status: InputStatus::Ok,
         }
     }
 
+    // Create a result for an input that has exceeded one of the limits, whose format is left undetermined.
+    fn with_limit_exceeded(input: &str, limit_exceeded: &LimitExceeded) -> Self {
+        let mut module_result = Self::with_result(None, None);
+        module_result.set_input(input.to_string());
+        module_result.set_is_ok(false);
+        module_result.set_error_message(limit_exceeded.message().to_string());
+        module_result.status = InputStatus::LimitExceeded;
+        module_result
+    }
+
+    // Report that the input has been cut off at the end, keeping the detected format.
+    fn set_truncated(&mut self, reason: String) {
+        self.error_message = Some(reason);
+        self.status = InputStatus::Truncated;
+    }
+
+    // Create a result for an input that could not be read or downloaded.
+    fn with_io_failure(input: &str, error: &anyhow::Error) -> Self {
+        let mut module_result = Self::with_result(None, None);
+        module_result.set_input(input.to_string());
+        module_result.set_is_ok(false);
+        module_result.set_error_message(format!("{:#}", error));
+        module_result.validation = Some(Validation {
+            status: ValidationStatus::IoFailure,
+            expected: Vec::new(),
+            reasons: Vec::new(),
+        });
+        module_result
+    }
+
+    // Attach the evidence collected by a parser, from which the confidence score is computed in `parser::invoke`.
+    pub const fn with_evidence(mut self, evidence: Evidence) -> Self {
+        self.evidence = Some(evidence);
+        self
+    }
+
+    // Attach the version of the format read by a parser from the header of the input.
+    pub fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    // Attach a property of the format read by a parser, such as the sort order of SAM or the number of columns of BED.
+    pub fn with_detail(mut self, key: &str, value: impl Into<Detail>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
     pub const fn label(&self) -> Option<&String> {
         self.label.as_ref()
     }
@@ -59,6 +208,22 @@ impl ModuleResult {
         self.error_message.as_ref()
     }
 
+    pub const fn error_location(&self) -> Option<&Location> {
+        self.error_location.as_ref()
+    }
+
+    pub const fn evidence(&self) -> Option<&Evidence> {
+        self.evidence.as_ref()
+    }
+
+    pub const fn confidence(&self) -> Option<f64> {
+        self.confidence
+    }
+
+    pub const fn set_confidence(&mut self, confidence: f64) {
+        self.confidence = Some(confidence);
+    }
+
     pub const fn set_is_ok(&mut self, is_ok: bool) {
         self.is_ok = is_ok;
     }
@@ -67,13 +232,203 @@ impl ModuleResult {
         self.error_message = Some(error_message);
     }
 
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
     pub fn set_input(&mut self, input: String) {
         self.input = input;
     }
 
+    // Set the results of the files in the archive, in the order they appear in it.
+    pub fn set_members(&mut self, members: Vec<Self>) {
+        self.members = Some(members);
+    }
+
+    // Set where in the input the parser failed.
+    pub fn set_error_location(&mut self, error_location: Location) {
+        self.error_location = Some(error_location);
+    }
+
+    // Set the per-module outcomes collected with `--all-matches`, and flag the result as ambiguous if more than one module matched.
+    fn set_candidates(&mut self, candidates: Vec<Candidate>) {
+        self.ambiguous = candidates.iter().filter(|c| c.is_ok).count() > 1;
+        self.candidates = Some(candidates);
+    }
+
+    // whether the format of the input, or the decompressed content if decompression has been done, has been determined.
+    fn is_format_detected(&self) -> bool {
+        match &self.decompressed {
+            Some(decompressed) => decompressed.id.is_some() || decompressed.label.is_some(),
+            None => self.id.is_some() || self.label.is_some(),
+        }
+    }
+
+    // whether the expected format is the detected one, the decompressed one, or one of the matched candidates.
+    fn matches_expected_format(&self, expected_format: &ExpectedFormat) -> bool {
+        let decompressed = self.decompressed.as_ref();
+        expected_format.matches(self.id.as_ref(), self.label.as_ref())
+            || expected_format.matches(
+                decompressed.and_then(|d| d.id.as_ref()),
+                decompressed.and_then(|d| d.label.as_ref()),
+            )
+            || self
+                .candidates
+                .iter()
+                .flatten()
+                .filter(|c| c.is_ok)
+                .any(|c| expected_format.matches(c.id.as_ref(), c.label.as_ref()))
+    }
+
+    // Check the detected format against the expected formats, and store the validation status along with the reasons of the failure.
+    fn validate(&mut self, expected_formats: &[ExpectedFormat]) {
+        let is_io_failure = self
+            .validation
+            .as_ref()
+            .is_some_and(|v| v.status == ValidationStatus::IoFailure);
+
+        let status = if is_io_failure {
+            ValidationStatus::IoFailure
+        } else if self.status == InputStatus::LimitExceeded {
+            ValidationStatus::LimitExceeded
+        } else if self.status == InputStatus::Truncated {
+            ValidationStatus::Truncated
+        } else if expected_formats.is_empty() {
+            if self.is_format_detected() {
+                ValidationStatus::Passed
+            } else {
+                ValidationStatus::NoFormatDetected
+            }
+        } else if expected_formats
+            .iter()
+            .any(|expected_format| self.matches_expected_format(expected_format))
+        {
+            ValidationStatus::Passed
+        } else if self.is_format_detected() {
+            ValidationStatus::FormatMismatch
+        } else {
+            ValidationStatus::NoFormatDetected
+        };
+
+        let reasons = match status {
+            ValidationStatus::Passed => Vec::new(),
+            ValidationStatus::IoFailure
+            | ValidationStatus::LimitExceeded
+            | ValidationStatus::Truncated => self.error_message.iter().cloned().collect(),
+            ValidationStatus::NoFormatDetected | ValidationStatus::FormatMismatch => {
+                // the "empty" module is not worth reporting as it is invoked for every input.
+                let failed_candidates = self
+                    .candidates
+                    .iter()
+                    .flatten()
+                    .filter(|c| !c.is_ok && c.module != "empty")
+                    .collect::<Vec<&Candidate>>();
+
+                // show the failures of the modules for the expected formats if any, otherwise show the failures of all modules.
+                let expected_candidates = failed_candidates
+                    .iter()
+                    .filter(|c| expected_formats.iter().any(|e| c.is_for(e)))
+                    .copied()
+                    .collect::<Vec<&Candidate>>();
+                let reported_candidates = if expected_candidates.is_empty() {
+                    failed_candidates
+                } else {
+                    expected_candidates
+                };
+
+                reported_candidates
+                    .iter()
+                    .map(|c| {
+                        format!(
+                            "{}: {}",
+                            c.module,
+                            c.error_message.as_deref().unwrap_or("").trim_end()
+                        )
+                    })
+                    .collect()
+            }
+        };
+
+        self.validation = Some(Validation {
+            status,
+            expected: expected_formats
+                .iter()
+                .map(|e| e.display_name().to_string())
+                .collect(),
+            reasons,
+        });
+    }
+
+    // Create a human-readable report of the validation results for the `validate` subcommand.
+    pub fn create_validation_report_string(module_results: &[Self]) -> String {
+        let mut report = String::new();
+        for module_result in module_results {
+            let Some(validation) = &module_result.validation else {
+                continue;
+            };
+
+            let detected_label = match &module_result.decompressed {
+                Some(decompressed) => format!(
+                    "{} > {}",
+                    module_result.label.as_deref().unwrap_or("unknown"),
+                    decompressed.label.as_deref().unwrap_or("unknown")
+                ),
+                None => module_result
+                    .label
+                    .as_deref()
+                    .unwrap_or("unknown")
+                    .to_string(),
+            };
+            let expected_labels = validation.expected.join(" or ");
+
+            let summary = match validation.status {
+                ValidationStatus::Passed => format!("detected {}", detected_label),
+                ValidationStatus::FormatMismatch => {
+                    format!("expected {}, detected {}", expected_labels, detected_label)
+                }
+                ValidationStatus::NoFormatDetected if !validation.expected.is_empty() => {
+                    format!("expected {}, but no format was detected", expected_labels)
+                }
+                ValidationStatus::NoFormatDetected => "no format was detected".to_string(),
+                ValidationStatus::Truncated => {
+                    format!("detected {}, but the file is truncated", detected_label)
+                }
+                ValidationStatus::IoFailure => "failed to read the input".to_string(),
+                ValidationStatus::LimitExceeded => "exceeded a limit".to_string(),
+            };
+
+            report.push_str(&format!(
+                "{}: {} ({})\n",
+                module_result.input, validation.status, summary
+            ));
+            for reason in &validation.reasons {
+                report.push_str(&format!("  - {}\n", reason.replace('\n', "\n    ")));
+            }
+        }
+
+        report.trim_end().to_string()
+    }
+
+    // The version of the detected format, which is the decompressed one if the input is compressed.
+    fn detected_version(&self) -> Option<&String> {
+        self.version
+            .as_ref()
+            .or_else(|| self.decompressed.as_ref()?.version.as_ref())
+    }
+
+    // The details of the detected format, which is the decompressed one if the input is compressed.
+    fn detected_details(&self) -> &BTreeMap<String, Detail> {
+        match &self.decompressed {
+            Some(decompressed) if self.details.is_empty() => &decompressed.details,
+            _ => &self.details,
+        }
+    }
+
     fn swap_edam_of_module_result_and_compressed_format(&mut self, compressed_format_edam: Self) {
         let tmp_label = self.label.to_owned();
         let tmp_id = self.id.to_owned();
+        let tmp_version = self.version.take();
+        let tmp_details = std::mem::take(&mut self.details);
 
         self.label = compressed_format_edam.label;
         self.id = compressed_format_edam.id;
@@ -81,6 +436,8 @@ impl ModuleResult {
         let tmp_decompressed = DecompressedFormat {
             label: tmp_label,
             id: tmp_id,
+            version: tmp_version,
+            details: tmp_details,
         };
         self.decompressed = Some(tmp_decompressed);
     }
@@ -88,36 +445,114 @@ impl ModuleResult {
     pub fn create_module_results_string(
         module_results: &[Self],
         format: OutputFormat,
+        show_details: bool,
     ) -> Result<String> {
-        fn csv_serialize(module_results: &[ModuleResult], delimiter: u8) -> Result<String> {
+        fn csv_serialize(
+            module_results: &[ModuleResult],
+            delimiter: u8,
+            show_details: bool,
+        ) -> Result<String> {
+            // the candidate columns are only shown when `--all-matches` has been used.
+            let show_candidates = module_results.iter().any(|m| m.candidates.is_some());
+            // the validation column is only shown when `--expect` has been used.
+            let show_validation = module_results.iter().any(|m| m.validation.is_some());
+            // the status column is only shown when any input has exceeded a limit or is truncated.
+            let show_status = flatten_members(module_results)
+                .iter()
+                .any(|(_, m)| m.status != InputStatus::Ok);
+            // the version and the details are only shown with `--details`, each detail in its own column.
+            let detail_keys = if show_details {
+                flatten_members(module_results)
+                    .iter()
+                    .flat_map(|(_, m)| m.detected_details().keys())
+                    .cloned()
+                    .collect::<BTreeSet<String>>()
+            } else {
+                BTreeSet::new()
+            };
+
             let mut data = Vec::new();
             {
                 let mut writer = csv::WriterBuilder::new()
                     .delimiter(delimiter)
                     .from_writer(&mut data);
 
-                writer.write_record([
+                let mut header = vec![
                     "File Path",
                     "Edam ID",
                     "Label",
                     "Decompressed ID",
                     "Decompressed Label",
-                ])?;
-
-                for module_result in module_results.iter() {
-                    writer.serialize((
-                        &module_result.input,
-                        &module_result.id,
-                        &module_result.label,
-                        &module_result
-                            .decompressed
-                            .as_ref()
-                            .and_then(|d| d.id.as_ref()),
-                        &module_result
-                            .decompressed
-                            .as_ref()
-                            .and_then(|d| d.label.as_ref()),
-                    ))?;
+                    "Confidence",
+                ];
+                if show_candidates {
+                    header.extend(["Candidates", "Ambiguous"]);
+                }
+                if show_validation {
+                    header.push("Validation");
+                }
+                if show_status {
+                    header.push("Status");
+                }
+                if show_details {
+                    header.push("Version");
+                    header.extend(detail_keys.iter().map(String::as_str));
+                }
+                writer.write_record(&header)?;
+
+                for (input, module_result) in flatten_members(module_results) {
+                    let decompressed = module_result.decompressed.as_ref();
+                    let mut record = vec![
+                        input,
+                        module_result.id.clone().unwrap_or_default(),
+                        module_result.label.clone().unwrap_or_default(),
+                        decompressed.and_then(|d| d.id.clone()).unwrap_or_default(),
+                        decompressed
+                            .and_then(|d| d.label.clone())
+                            .unwrap_or_default(),
+                        module_result
+                            .confidence
+                            .map(|c| c.to_string())
+                            .unwrap_or_default(),
+                    ];
+                    if show_candidates {
+                        // list the labels of the matched modules separated by semicolons.
+                        let matched_labels = module_result
+                            .candidates
+                            .iter()
+                            .flatten()
+                            .filter(|c| c.is_ok)
+                            .filter_map(|c| c.label.clone())
+                            .collect::<Vec<String>>()
+                            .join(";");
+                        record.push(matched_labels);
+                        record.push(module_result.ambiguous.to_string());
+                    }
+                    if show_validation {
+                        record.push(
+                            module_result
+                                .validation
+                                .as_ref()
+                                .map(|v| v.status.to_string())
+                                .unwrap_or_default(),
+                        );
+                    }
+                    if show_status {
+                        record.push(module_result.status.to_string());
+                    }
+                    if show_details {
+                        record.push(
+                            module_result
+                                .detected_version()
+                                .cloned()
+                                .unwrap_or_default(),
+                        );
+                        let details = module_result.detected_details();
+                        record.extend(detail_keys.iter().map(|key| {
+                            details.get(key).map(Detail::to_string).unwrap_or_default()
+                        }));
+                    }
+                    writer.write_record(&record)?;
                 }
             }
 
@@ -126,167 +561,202 @@ impl ModuleResult {
         }
 
         match format {
-            OutputFormat::Yaml => {
-                let mut serialized_map: HashMap<String, serde_yaml::Value> = HashMap::new();
-                for module_result in module_results {
-                    let target_file_path = &module_result.input;
-
-                    // create yaml map for decompressed field
-                    let mut de_map: HashMap<String, serde_yaml::Value> = HashMap::new();
-                    match &module_result.decompressed {
-                        Some(decompressed) => {
-                            match &decompressed.id {
-                                Some(id) => {
-                                    de_map.insert(
-                                        "id".to_string(),
-                                        serde_yaml::Value::String(id.clone()),
-                                    );
-                                }
-                                None => {
-                                    de_map.insert("id".to_string(), serde_yaml::Value::Null);
-                                }
-                            }
-                            match &decompressed.label {
-                                Some(label) => {
-                                    de_map.insert(
-                                        "label".to_string(),
-                                        serde_yaml::Value::String(label.clone()),
-                                    );
-                                }
-                                None => {
-                                    de_map.insert("label".to_string(), serde_yaml::Value::Null);
-                                }
-                            }
-                        }
-                        None => {
-                            de_map.insert("id".to_string(), serde_yaml::Value::Null);
-                            de_map.insert("label".to_string(), serde_yaml::Value::Null);
-                        }
-                    }
+            OutputFormat::Yaml => Ok(serde_yaml::to_string(&ResultsByInput(module_results))?),
+            OutputFormat::Json => Ok(serde_json::to_string(&ResultsByInput(module_results))?),
+            OutputFormat::Tsv => csv_serialize(module_results, b'\t', show_details),
+            OutputFormat::Csv => csv_serialize(module_results, b',', show_details),
+            OutputFormat::Ndjson => {
+                let lines = module_results
+                    .iter()
+                    .map(|module_result| {
+                        serde_json::to_string(&ResultEntry::new(module_result, true))
+                    })
+                    .collect::<Result<Vec<String>, _>>()?;
+                Ok(lines.join("\n"))
+            }
+            OutputFormat::JsonArray => {
+                let entries: Vec<ResultEntry> = module_results
+                    .iter()
+                    .map(|module_result| ResultEntry::new(module_result, true))
+                    .collect();
+                Ok(serde_json::to_string(&entries)?)
+            }
+        }
+    }
+}
 
-                    // create yaml map for label and id fields
-                    let mut comp_map: HashMap<String, serde_yaml::Value> = HashMap::new();
-                    match &module_result.id {
-                        Some(id) => {
-                            comp_map
-                                .insert("id".to_string(), serde_yaml::Value::String(id.clone()));
-                        }
-                        None => {
-                            comp_map.insert("id".to_string(), serde_yaml::Value::Null);
-                        }
-                    }
-                    match &module_result.label {
-                        Some(label) => {
-                            comp_map.insert(
-                                "label".to_string(),
-                                serde_yaml::Value::String(label.clone()),
-                            );
-                        }
-                        None => {
-                            comp_map.insert("label".to_string(), serde_yaml::Value::Null);
-                        }
-                    }
+// The result of a single input as serialized in YAML and JSON. Fields are serialized in the order of declaration.
+#[derive(Serialize)]
+struct ResultEntry<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<&'a str>,
+    label: Option<&'a String>,
+    id: Option<&'a String>,
+    // only present if the parser tells the version of the format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<&'a String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    details: &'a BTreeMap<String, Detail>,
+    confidence: Option<f64>,
+    decompressed: &'a DecompressedFormat,
+    // only present if the input has been compressed more than once
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    compression_layers: &'a [CompressionLayer],
+    // only present if `--all-matches` has been used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    candidates: Option<&'a Vec<Candidate>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ambiguous: Option<bool>,
+    // only present if `--expect` has been used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validation: Option<&'a Validation>,
+    // only present if the input has exceeded one of the limits or is truncated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<InputStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a String>,
+    errors: &'a [ModuleError],
+    // only present if the input is an archive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    members: Option<Members<'a>>,
+    // only present in the map form if the same input has been given more than once, holding the results of the later ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    duplicates: Vec<ResultEntry<'a>>,
+}
 
-                    // add decompressed field to the yaml map
-                    comp_map.insert("decompressed".to_string(), serde_yaml::to_value(de_map)?);
-                    // match &module_result.decompressed {
-                    //     Some(decompressed) => {
-                    //         comp_map.insert("decompressed".to_string(), serde_yaml::to_value(decompressed)?);
-                    //     },
-                    //     None => {
-                    //         comp_map.insert("decompressed".to_string(), serde_yaml::Value::Null);
-                    //     }
-                    // }
-
-                    serialized_map
-                        .insert(target_file_path.clone(), serde_yaml::to_value(comp_map)?);
-                }
+// The results of the files in an archive, keyed by their paths in the archive, or as an array in the array form.
+struct Members<'a> {
+    results: &'a [ModuleResult],
+    with_input: bool,
+}
 
-                let yaml_str = serde_yaml::to_string(&serialized_map)?;
-                Ok(yaml_str)
-            }
-            OutputFormat::Tsv => csv_serialize(module_results, b'\t'),
-            OutputFormat::Csv => csv_serialize(module_results, b','),
-            OutputFormat::Json => {
-                let mut serialized_map: HashMap<String, serde_json::Value> = HashMap::new();
-                for module_result in module_results {
-                    let target_file_path = &module_result.input;
-
-                    // create json map for decompressed field
-                    let mut de_map: HashMap<String, serde_json::Value> = HashMap::new();
-                    match &module_result.decompressed {
-                        Some(decompressed) => {
-                            match &decompressed.id {
-                                Some(id) => {
-                                    de_map.insert(
-                                        "id".to_string(),
-                                        serde_json::Value::String(id.clone()),
-                                    );
-                                }
-                                None => {
-                                    de_map.insert("id".to_string(), serde_json::Value::Null);
-                                }
-                            }
-                            match &decompressed.label {
-                                Some(label) => {
-                                    de_map.insert(
-                                        "label".to_string(),
-                                        serde_json::Value::String(label.clone()),
-                                    );
-                                }
-                                None => {
-                                    de_map.insert("label".to_string(), serde_json::Value::Null);
-                                }
-                            }
-                        }
-                        None => {
-                            de_map.insert("id".to_string(), serde_json::Value::Null);
-                            de_map.insert("label".to_string(), serde_json::Value::Null);
-                        }
-                    }
+impl Serialize for Members<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if self.with_input {
+            serializer.collect_seq(
+                self.results
+                    .iter()
+                    .map(|module_result| ResultEntry::new(module_result, true)),
+            )
+        } else {
+            ResultsByInput(self.results).serialize(serializer)
+        }
+    }
+}
 
-                    // create json map for label and id fields
-                    let mut comp_map: HashMap<String, serde_json::Value> = HashMap::new();
-                    match &module_result.id {
-                        Some(id) => {
-                            comp_map
-                                .insert("id".to_string(), serde_json::Value::String(id.clone()));
-                        }
-                        None => {
-                            comp_map.insert("id".to_string(), serde_json::Value::Null);
-                        }
-                    }
-                    match &module_result.label {
-                        Some(label) => {
-                            comp_map.insert(
-                                "label".to_string(),
-                                serde_json::Value::String(label.clone()),
-                            );
-                        }
-                        None => {
-                            comp_map.insert("label".to_string(), serde_json::Value::Null);
-                        }
-                    }
+// List the results with the results of the files in archives following each archive, as rows of CSV and TSV.
+// The files in an archive are shown as `<archive>!<path in the archive>`.
+fn flatten_members(module_results: &[ModuleResult]) -> Vec<(String, &ModuleResult)> {
+    let mut rows = Vec::new();
+    for module_result in module_results {
+        rows.push((module_result.input.clone(), module_result));
+        for (member_input, member_result) in
+            flatten_members(module_result.members.as_deref().unwrap_or_default())
+        {
+            rows.push((
+                format!("{}!{}", module_result.input, member_input),
+                member_result,
+            ));
+        }
+    }
+    rows
+}
 
-                    // add components field to the json map
-                    comp_map.insert("decompressed".to_string(), serde_json::to_value(de_map)?);
-                    // match &module_result.decompressed {
-                    //     Some(decompressed) => {
-                    //         comp_map.insert("decompressed".to_string(), serde_json::to_value(decompressed)?);
-                    //     },
-                    //     None => {
-                    //         comp_map.insert("decompressed".to_string(), serde_json::Value::Null);
-                    //     }
-                    // }
-
-                    serialized_map
-                        .insert(target_file_path.clone(), serde_json::to_value(comp_map)?);
-                }
+static NOT_DECOMPRESSED: DecompressedFormat = DecompressedFormat {
+    label: None,
+    id: None,
+    version: None,
+    details: BTreeMap::new(),
+};
+
+impl<'a> ResultEntry<'a> {
+    // `with_input` is set for the array form, in which the input is a field of the entry instead of its key.
+    fn new(module_result: &'a ModuleResult, with_input: bool) -> Self {
+        Self {
+            input: with_input.then_some(module_result.input.as_str()),
+            label: module_result.label.as_ref(),
+            id: module_result.id.as_ref(),
+            version: module_result.version.as_ref(),
+            details: &module_result.details,
+            confidence: module_result.confidence,
+            decompressed: module_result
+                .decompressed
+                .as_ref()
+                .unwrap_or(&NOT_DECOMPRESSED),
+            compression_layers: &module_result.compression_layers,
+            candidates: module_result.candidates.as_ref(),
+            ambiguous: module_result
+                .candidates
+                .as_ref()
+                .map(|_| module_result.ambiguous),
+            validation: module_result.validation.as_ref(),
+            status: (module_result.status != InputStatus::Ok).then_some(module_result.status),
+            reason: (module_result.status != InputStatus::Ok)
+                .then_some(module_result.error_message.as_ref())
+                .flatten(),
+            errors: &module_result.errors,
+            members: module_result.members.as_deref().map(|results| Members {
+                results,
+                with_input,
+            }),
+            duplicates: Vec::new(),
+        }
+    }
+}
 
-                let json_str = serde_json::to_string(&serialized_map)?;
-                Ok(json_str)
+// Results keyed by the inputs, in the order the inputs have been given.
+// The results of an input given more than once are grouped under its key, with the later ones in `duplicates` of the first one.
+struct ResultsByInput<'a>(&'a [ModuleResult]);
+
+impl Serialize for ResultsByInput<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut entries: Vec<(&str, ResultEntry)> = Vec::new();
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        for module_result in self.0 {
+            let entry = ResultEntry::new(module_result, false);
+            match indices.get(module_result.input.as_str()) {
+                Some(&index) => entries[index].1.duplicates.push(entry),
+                None => {
+                    indices.insert(module_result.input.as_str(), entries.len());
+                    entries.push((&module_result.input, entry));
+                }
             }
         }
+
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (input, entry) in &entries {
+            map.serialize_entry(input, entry)?;
+        }
+        map.end()
+    }
+}
+
+// A property of the format read by a parser, which is either a number, such as the number of columns of BED, or a text, such as the sort order of SAM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Detail {
+    Number(usize),
+    Text(String),
+}
+
+impl From<usize> for Detail {
+    fn from(number: usize) -> Self {
+        Self::Number(number)
+    }
+}
+
+impl From<String> for Detail {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl std::fmt::Display for Detail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{}", number),
+            Self::Text(text) => write!(f, "{}", text),
+        }
     }
 }
 
@@ -294,6 +764,231 @@ impl ModuleResult {
 pub struct DecompressedFormat {
     label: Option<String>,
     id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<String, Detail>,
+}
+
+// Struct to store why a module failed to determine the format of the input.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModuleError {
+    module: String,
+    message: String,
+    location: Option<Location>,
+}
+
+impl ModuleError {
+    fn new(module: &str, module_result: &ModuleResult) -> Self {
+        Self {
+            module: module.to_string(),
+            message: module_result.error_message.clone().unwrap_or_default(),
+            location: module_result.error_location.clone(),
+        }
+    }
+}
+
+// Struct to store the outcome of a single module invocation when `--all-matches` is enabled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Candidate {
+    module: String,
+    is_ok: bool,
+    label: Option<String>,
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    details: BTreeMap<String, Detail>,
+    confidence: Option<f64>,
+    error_message: Option<String>,
+}
+
+impl Candidate {
+    fn new(module: &str, module_result: ModuleResult) -> Self {
+        Self {
+            module: module.to_string(),
+            is_ok: module_result.is_ok,
+            label: module_result.label,
+            id: module_result.id,
+            version: module_result.version,
+            details: module_result.details,
+            confidence: module_result.confidence,
+            error_message: module_result.error_message,
+        }
+    }
+
+    // whether this module is the one that detects the expected format. Built-in parsers are named after the labels of their formats.
+    fn is_for(&self, expected_format: &ExpectedFormat) -> bool {
+        expected_format.matches(self.id.as_ref(), self.label.as_ref())
+            || self
+                .module
+                .eq_ignore_ascii_case(expected_format.display_name())
+            || self.module.eq_ignore_ascii_case(&expected_format.raw)
+    }
+}
+
+// Outcome of checking the detected format against the formats given by `--expect`.
+// The variants are ordered by severity, and the most severe one among the inputs determines the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationStatus {
+    Passed,
+    NoFormatDetected,
+    FormatMismatch,
+    Truncated,
+    IoFailure,
+    LimitExceeded,
+}
+
+impl ValidationStatus {
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            Self::Passed => 0,
+            Self::NoFormatDetected => 3,
+            Self::FormatMismatch => 4,
+            Self::IoFailure => 5,
+            Self::LimitExceeded => 6,
+            Self::Truncated => 7,
+        }
+    }
+
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::NoFormatDetected => "no_format_detected",
+            Self::FormatMismatch => "format_mismatch",
+            Self::IoFailure => "io_failure",
+            Self::LimitExceeded => "limit_exceeded",
+            Self::Truncated => "truncated",
+        }
+    }
+}
+
+impl std::fmt::Display for InputStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::LimitExceeded => "limit_exceeded",
+            Self::Truncated => "truncated",
+        })
+    }
+}
+
+impl std::fmt::Display for ValidationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Validation {
+    status: ValidationStatus,
+    expected: Vec<String>,
+    reasons: Vec<String>,
+}
+
+// A format given by `--expect`, resolved into EDAM ID and label if it exists in the EDAM table.
+#[derive(Debug)]
+pub struct ExpectedFormat {
+    raw: String,
+    id: Option<String>,
+    label: Option<String>,
+}
+
+impl ExpectedFormat {
+    fn new(raw: &str) -> Self {
+        match crate::edam::EDAM_MAP.resolve(raw) {
+            Some((id, label)) => Self {
+                raw: raw.to_string(),
+                id: Some(id),
+                label: Some(label),
+            },
+            None => {
+                warn!(
+                    "The expected format '{}' is not found in the EDAM table. It is compared with the ID and label of the detected format as is.",
+                    raw
+                );
+                Self {
+                    raw: raw.to_string(),
+                    id: None,
+                    label: None,
+                }
+            }
+        }
+    }
+
+    fn display_name(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.raw)
+    }
+
+    fn matches(&self, id: Option<&String>, label: Option<&String>) -> bool {
+        let id_matches = id.is_some_and(|id| self.id.as_ref() == Some(id) || *id == self.raw);
+        let label_matches = label.is_some_and(|label| {
+            self.label.as_ref() == Some(label) || label.eq_ignore_ascii_case(&self.raw)
+        });
+        id_matches || label_matches
+    }
+}
+
+// Struct to store the expected formats given by `--expect`, either for all inputs or for a specific input.
+pub struct Expectations {
+    global: Vec<ExpectedFormat>,
+    per_input: HashMap<String, Vec<ExpectedFormat>>,
+}
+
+impl Expectations {
+    pub fn new(expectations: &[Expectation]) -> Self {
+        let mut global = Vec::new();
+        let mut per_input: HashMap<String, Vec<ExpectedFormat>> = HashMap::new();
+        for expectation in expectations {
+            let expected_format = ExpectedFormat::new(&expectation.format);
+            match &expectation.input {
+                Some(input) => per_input
+                    .entry(input.clone())
+                    .or_default()
+                    .push(expected_format),
+                None => global.push(expected_format),
+            }
+        }
+        Self { global, per_input }
+    }
+
+    // The expectations specific to the input take precedence over the ones for the directory or the glob pattern it has been found by, and then the global ones.
+    pub fn for_input(&self, input: &Input) -> &[ExpectedFormat] {
+        self.per_input
+            .get(&input.path)
+            .or_else(|| self.per_input.get(&input.origin))
+            .unwrap_or(&self.global)
+    }
+}
+
+// Struct to store what a parser has observed while reading the input. It is used to compute the confidence score of the detection.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct Evidence {
+    /// Number of records read successfully
+    pub records: usize,
+    /// Whether the header of the format has been read and validated
+    pub header: bool,
+    /// Whether the magic bytes of the format were present
+    pub magic: bool,
+}
+
+impl Evidence {
+    /// Number of records at which the contribution of the records to the score saturates
+    const SATURATING_RECORDS: f64 = 10000.0;
+
+    // Compute the confidence score ranging from 0.0 to 1.0.
+    // The number of records contributes up to 0.6 on a logarithmic scale, and a validated header and magic bytes contribute 0.2 each.
+    pub fn score(&self) -> f64 {
+        let records_ratio = ((self.records as f64 + 1.0).log10()
+            / (Self::SATURATING_RECORDS + 1.0).log10())
+        .min(1.0);
+        let header_ratio = if self.header { 1.0 } else { 0.0 };
+        let magic_ratio = if self.magic { 1.0 } else { 0.0 };
+
+        let score = 0.6 * records_ratio + 0.2 * header_ratio + 0.2 * magic_ratio;
+        (score * 1000.0).round() / 1000.0
+    }
 }
 
 // Struct to deserialize the contents of the conf file.
@@ -302,6 +997,7 @@ pub struct Config {
     order: Vec<String>,
 }
 
+#[derive(Clone)]
 pub struct InvokeOptions {
     /// Read full content of the input or not
     pub tidy: bool,
@@ -309,110 +1005,187 @@ pub struct InvokeOptions {
     pub no_decompress: bool,
     /// Number of records to read
     pub num_records: usize,
+    /// Invoke every module instead of stopping at the first match
+    pub all_matches: bool,
+    /// Invoke the built-in parsers concurrently
+    pub parallel_modules: bool,
+    /// Maximum number of compression layers to decompress
+    pub decompress_depth: usize,
+    /// Limits on decompression, downloading and time for each input
+    pub limits: Limits,
+    /// Time by which the current input must have been processed, set for each input from `limits.input_timeout`
+    pub deadline: Option<Deadline>,
+    /// Options for the inputs given as URLs
+    pub fetch: FetchOptions,
+    /// FASTA file of the reference sequences for CRAM
+    pub reference: Option<PathBuf>,
+    /// Check the records against the semantics of the format beyond the syntax
+    pub strict: bool,
 }
 
-impl From<&Args> for InvokeOptions {
-    fn from(args: &Args) -> Self {
-        Self {
+impl TryFrom<&Args> for InvokeOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &Args) -> Result<Self> {
+        Ok(Self {
             tidy: args.tidy,
             no_decompress: args.no_decompress,
             num_records: args.num_records,
+            all_matches: args.all_matches,
+            parallel_modules: args.parallel_modules,
+            decompress_depth: args.decompress_depth,
+            limits: Limits::from(args),
+            deadline: None,
+            fetch: FetchOptions::try_from(args)?,
+            reference: args.reference.clone(),
+            strict: args.strict,
+        })
+    }
+}
+
+impl InvokeOptions {
+    // The options for processing a single input, whose deadline starts now.
+    fn for_input(&self) -> Self {
+        Self {
+            deadline: self.limits.input_timeout.map(Deadline::after),
+            ..self.clone()
         }
     }
 }
 
-pub fn run(config: Config, args: Args) -> Result<()> {
+pub fn run(config: Config, args: Args) -> Result<ExitCode> {
     crate::logger::init_logger(args.verbose, args.quiet);
     info!("tataki started");
     debug!("Args: {:?}", args);
     debug!("Output format: {:?}", args.get_output_format());
 
-    let invoke_options = InvokeOptions::from(&args);
+    if let Some(Command::Cache { command }) = &args.command {
+        crate::cache::run_command(command, args.cache_dir.as_deref())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut invoke_options = InvokeOptions::try_from(&args)?;
+
+    // the `validate` subcommand needs the outcome of every module to explain why the expectation failed.
+    if args.is_validate() {
+        invoke_options.all_matches = true;
+    }
+
+    // the inputs given by `--from-file` follow the ones given as arguments.
+    let mut arguments = args.inputs().to_vec();
+    let mut expectation_args = args.expect.clone();
+    if let Some(list_path) = &args.from_file {
+        let input_list = read_input_list(list_path)?;
+        arguments.extend(input_list.inputs);
+        expectation_args.extend(input_list.expectations);
+        if list_path == "-" && arguments.iter().any(|input| input == "-") {
+            bail!("STDIN cannot be used as an input when the list of inputs is read from STDIN.");
+        }
+    }
+
+    let expectations = Expectations::new(&expectation_args);
+    let validation_enabled = args.is_validate() || !expectation_args.is_empty();
 
     let cwl_module_exists = cwl_module_exists(&config)?;
 
     // validate the user-provided options and input arguments to ensure they are suitable for execution.
-    check_run_condition_cwl_module(&args.input, cwl_module_exists, &invoke_options)?;
+    check_run_condition_cwl_module(&arguments, cwl_module_exists, &invoke_options)?;
+
+    // expand directories and glob patterns into the files in them.
+    let inputs = discover_inputs(&arguments, &DiscoverOptions::try_from(&args)?)?;
 
     let temp_dir = crate::fetch::create_temporary_dir(&args.cache_dir)?;
     info!("Created temporary directory: {}", temp_dir.path().display());
 
+    // the downloads and the results are reused across the runs with the same cache directory.
+    let cache = match &args.cache_dir {
+        Some(cache_dir) if !args.no_cache => Some(Cache::open(
+            cache_dir,
+            fingerprint(&config, &invoke_options)?,
+        )?),
+        _ => None,
+    };
+
     // create an empty vector to store the results of each module invocation.
     let mut module_results: Vec<ModuleResult> = Vec::new();
 
+    // with NDJSON, the result of each input is written as soon as it is determined instead of after all inputs.
+    let mut stream: Option<Box<dyn Write>> =
+        if !args.is_validate() && matches!(args.get_output_format(), OutputFormat::Ndjson) {
+            match &args.output {
+                Some(output_path) => {
+                    info!("Writing the result to {}", output_path.display());
+                    Some(Box::new(File::create(output_path)?))
+                }
+                None => Some(Box::new(std::io::stdout())),
+            }
+        } else {
+            None
+        };
+
     // insert "empty" module at the beginning of the module order, so that the empty module is always invoked first.
     let mut config = config;
     config.order.insert(0, "empty".to_string());
 
-    for input in &args.input {
-        let mut input = input.clone();
-        info!("Processing input: {}", input);
-
-        // Check if the input is stdin or path. If path, download the file if it is a url.
-        let (target_source, compressed_format) = match input.parse::<Source>()? {
-            Source::FilePath(p) => {
-                // Prepare input file path from url or local file path.
-                // Download the file and store it in the specified cache directory if input is url.
-                let target_file_path = match Url::parse(&p.to_string_lossy()).ok() {
-                    Some(url) => {
-                        info!("Downloading from {}", url);
-                        let path = crate::fetch::download_from_url(&url, &temp_dir)?;
-                        info!("Downloaded to {}", path.display());
-                        path
+    let process_input = |input: &Input| -> Result<ModuleResult> {
+        info!("Processing input: {}", input.path);
+
+        let mut module_result = match determine_input(
+            &input.path,
+            &config,
+            &temp_dir,
+            &invoke_options.for_input(),
+            cwl_module_exists,
+            cache.as_ref(),
+        ) {
+            Ok(module_result) => module_result,
+            // the input exceeding a limit is reported as such, and the rest of the inputs are processed regardless of validation.
+            Err(e) if find_limit_exceeded(&e).is_some() || validation_enabled => {
+                error!(
+                    "Failed to process the input {}. Reason:\n{:#}",
+                    input.path, e
+                );
+                match find_limit_exceeded(&e) {
+                    Some(limit_exceeded) => {
+                        ModuleResult::with_limit_exceeded(&input.path, limit_exceeded)
                     }
-                    None => {
-                        let path = PathBuf::from(&input);
-                        if !path.exists() {
-                            bail!("The specified target file does not exist. Please check the path. : {}" ,path.display()
-                            );
-                        }
-                        path
-                    }
-                };
-
-                let (source, compressed_format) =
-                    Source::decompress_into_tempfile_from_filepath_if_needed(
-                        &target_file_path,
-                        &invoke_options,
-                        &temp_dir,
-                        cwl_module_exists,
-                    )?;
-
-                match source {
-                    Some(source) => (source, compressed_format),
-                    None => (Source::FilePath(target_file_path), compressed_format),
+                    // keep going with the rest of the inputs and report the failure if the formats are being validated.
+                    None => ModuleResult::with_io_failure(&input.path, &e),
                 }
             }
-            Source::Stdin => {
-                info!("Reading from STDIN...");
-                input = "STDIN".to_string();
-                Source::convert_into_tempfile_from_stdin(&invoke_options, &temp_dir)?
-            }
-            Source::TempFile(_) => unreachable!(),
-            Source::Memory(_) => unreachable!(),
+            Err(e) => return Err(e),
         };
 
-        let mut module_result = run_modules(target_source, &config, &temp_dir, &invoke_options)?;
+        if validation_enabled {
+            module_result.validate(expectations.for_input(input));
+        }
 
-        let compressed_format_edam = ModuleResult::from(&compressed_format);
-        // must swap the edam of the module result and the compressed format if decompress has been done.
-        match compressed_format {
-            CompressedFormat::None => {}
-            CompressedFormat::Bgzf => {}
-            _ => {
-                module_result
-                    .swap_edam_of_module_result_and_compressed_format(compressed_format_edam);
-            }
+        Ok(module_result)
+    };
+
+    let emit_result = |module_result: ModuleResult| -> Result<()> {
+        if let Some(stream) = stream.as_mut() {
+            let line = ModuleResult::create_module_results_string(
+                std::slice::from_ref(&module_result),
+                OutputFormat::Ndjson,
+                args.details,
+            )?;
+            writeln!(stream, "{}", line)?;
+            stream.flush()?;
         }
 
-        module_result.set_input(input.clone());
         module_results.push(module_result);
-    }
+        Ok(())
+    };
+
+    process_inputs(&inputs, args.jobs, process_input, emit_result)?;
 
     // if args.cache_dir is Some, keep the temporary directory.
     // Otherwise, delete the temporary directory.
     if args.cache_dir.is_some() {
-        info!("Keeping temporary directory: {}", temp_dir.keep().display());
+        // keep the directory outside of the macro, which skips evaluating its arguments when the log is disabled.
+        let kept_dir = temp_dir.keep();
+        info!("Keeping temporary directory: {}", kept_dir.display());
     } else {
         info!(
             "Deleting temporary directory: {}",
@@ -421,18 +1194,455 @@ pub fn run(config: Config, args: Args) -> Result<()> {
         temp_dir.close()?;
     }
 
-    let result_str =
-        ModuleResult::create_module_results_string(&module_results, args.get_output_format())?;
+    // exit with the code of the most severe validation status among the inputs. An input exceeding a limit or truncated counts as such without validation as well.
+    let exit_code = module_results
+        .iter()
+        .filter_map(|module_result| match &module_result.validation {
+            Some(validation) => Some(validation.status),
+            None => match module_result.status {
+                InputStatus::Ok => None,
+                InputStatus::LimitExceeded => Some(ValidationStatus::LimitExceeded),
+                InputStatus::Truncated => Some(ValidationStatus::Truncated),
+            },
+        })
+        .max()
+        .map_or(0, ValidationStatus::exit_code);
+
+    // the results have already been written.
+    if stream.is_some() {
+        return Ok(ExitCode::from(exit_code));
+    }
+
+    let result_str = if args.is_validate() {
+        ModuleResult::create_validation_report_string(&module_results)
+    } else {
+        ModuleResult::create_module_results_string(
+            &module_results,
+            args.get_output_format(),
+            args.details,
+        )?
+    };
 
     // if args.output is Some, write the result to the specified file. Otherwise, write the result to stdout.
-    if let Some(output_path) = args.output {
+    if let Some(output_path) = &args.output {
         info!("Writing the result to {}", output_path.display());
         std::fs::write(output_path, result_str)?;
     } else {
         println!("{}", result_str);
     }
 
-    Ok(())
+    Ok(ExitCode::from(exit_code))
+}
+
+// Process the inputs with a pool of `jobs` worker threads, and hand over the results to `emit` in the order of the inputs.
+// The first error in the order of the inputs stops the processing, as with processing them one by one.
+fn process_inputs<P, E>(inputs: &[Input], jobs: usize, process: P, mut emit: E) -> Result<()>
+where
+    P: Fn(&Input) -> Result<ModuleResult> + Sync,
+    E: FnMut(ModuleResult) -> Result<()>,
+{
+    if jobs <= 1 || inputs.len() <= 1 {
+        for input in inputs {
+            emit(process(input)?)?;
+        }
+        return Ok(());
+    }
+
+    let next_index = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs.min(inputs.len()) {
+            let sender = sender.clone();
+            let (next_index, cancelled, process) = (&next_index, &cancelled, &process);
+            scope.spawn(move || {
+                while !cancelled.load(Ordering::Relaxed) {
+                    let index = next_index.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(index) else {
+                        break;
+                    };
+                    if sender.send((index, process(input))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // results that have arrived ahead of the ones for the preceding inputs.
+        let mut pending: BTreeMap<usize, Result<ModuleResult>> = BTreeMap::new();
+        let mut next_to_emit = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&next_to_emit) {
+                next_to_emit += 1;
+                if let Err(e) = result.and_then(&mut emit) {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// Determine the format of a single input, which is either a local file path, a URL or "-" for STDIN.
+fn determine_input(
+    input: &str,
+    config: &Config,
+    temp_dir: &TempDir,
+    invoke_options: &InvokeOptions,
+    cwl_module_exists: bool,
+    cache: Option<&Cache>,
+) -> Result<ModuleResult> {
+    let mut input = input.to_string();
+    // the key of the content in the cache, which is known for local files and for URLs whose server tells the ETag or the modification time.
+    let mut content_key = None;
+    // why the input is truncated, which is checked for the files in BGZF.
+    let mut truncation_reason = None;
+
+    // Check if the input is stdin or path. If path, download the file if it is a url.
+    let (target_source, compression_layers) = match input.parse::<Source>()? {
+        Source::FilePath(p) => {
+            // Prepare input file path from url or local file path.
+            // Download the file and store it in the specified cache directory if input is url.
+            let target_file_path = match Url::parse(&p.to_string_lossy()).ok() {
+                Some(url) if url.scheme() != "file" => {
+                    info!("Downloading from {}", url);
+                    let mut download = crate::fetch::open_url(&url, temp_dir, invoke_options)?;
+                    if let (Some(cache), Some(validator)) = (cache, download.validator()) {
+                        let url_key = Cache::url_key(&url, validator)?;
+                        if let Some(module_result) = find_cached_result(cache, &url_key, &input) {
+                            return Ok(module_result);
+                        }
+                        content_key = Some(url_key);
+                    }
+                    let cached_path = content_key
+                        .as_deref()
+                        .zip(cache)
+                        .and_then(|(url_key, cache)| cache.find_download(url_key));
+
+                    match cached_path {
+                        Some(cached_path) => {
+                            info!(
+                                "Reusing the file downloaded before: {}",
+                                cached_path.display()
+                            );
+                            cached_path
+                        }
+                        None => {
+                            let (header, mut reader) = peek(&mut download)?;
+
+                            // the whole file is needed to read it to the end, for CWL modules, to parse it without decompression, and to look into archives.
+                            let needs_whole_file = invoke_options.tidy
+                                || cwl_module_exists
+                                || invoke_options.no_decompress
+                                || detect_archive_in_header(&header).is_some();
+                            if !needs_whole_file {
+                                // sample the records while downloading, and stop downloading once the sample is complete.
+                                let (source, compression_layers) =
+                                    Source::read_from_reader(reader, invoke_options, temp_dir)?;
+                                let truncation_reason =
+                                    check_download_truncation(&header, &mut download, &input);
+                                let path = download.keep()?;
+                                info!("Downloaded to {}", path.display());
+                                let mut module_result = determine_source(
+                                    input,
+                                    source,
+                                    compression_layers,
+                                    config,
+                                    temp_dir,
+                                    invoke_options,
+                                )?;
+                                if let Some(reason) = truncation_reason {
+                                    module_result.set_truncated(reason);
+                                }
+                                store_cached_result(cache, content_key.as_deref(), &module_result);
+                                return Ok(module_result);
+                            }
+
+                            std::io::copy(&mut reader, &mut std::io::sink())?;
+                            drop(reader);
+                            let validator = download.validator().map(str::to_string);
+                            let path = download.keep()?;
+                            info!("Downloaded to {}", path.display());
+                            if let (Some(cache), Some(url_key), Some(validator)) =
+                                (cache, &content_key, validator)
+                            {
+                                if let Err(e) =
+                                    cache.store_download(url_key, &url, &validator, &path)
+                                {
+                                    warn!(
+                                        "Failed to cache the file downloaded from {}: {:#}",
+                                        url, e
+                                    );
+                                }
+                            }
+                            path
+                        }
+                    }
+                }
+                url => {
+                    // a `file://` URL is read as the local file it points to.
+                    let path = match url {
+                        Some(url) => crate::fetch::file_url_to_path(&url)?,
+                        None => PathBuf::from(&input),
+                    };
+                    if !path.exists() {
+                        bail!(
+                            "The specified target file does not exist. Please check the path. : {}",
+                            path.display()
+                        );
+                    }
+                    if let Some(cache) = cache {
+                        let file_key = cache.file_key(&path)?;
+                        if let Some(module_result) = find_cached_result(cache, &file_key, &input) {
+                            return Ok(module_result);
+                        }
+                        content_key = Some(file_key);
+                    }
+                    path
+                }
+            };
+
+            // the files in an archive are determined one by one, unless the input is handled as is.
+            if !invoke_options.no_decompress {
+                if let Some((archive_format, compressed_format)) =
+                    detect_archive(&target_file_path)?
+                {
+                    let mut module_result = determine_archive(
+                        &target_file_path,
+                        archive_format,
+                        compressed_format,
+                        config,
+                        temp_dir,
+                        invoke_options,
+                        cwl_module_exists,
+                    )?;
+                    module_result.set_input(input);
+                    store_cached_result(cache, content_key.as_deref(), &module_result);
+                    return Ok(module_result);
+                }
+            }
+
+            truncation_reason = check_truncation(&target_file_path, &input);
+
+            let (source, compression_layers) = match Source::decompress_from_filepath_if_needed(
+                &target_file_path,
+                invoke_options,
+                temp_dir,
+                cwl_module_exists,
+            ) {
+                Ok(decompressed) => decompressed,
+                // the broken end of a truncated file fails the decompression, so the file is parsed as is and reported as truncated.
+                Err(e) if truncation_reason.is_some() && find_limit_exceeded(&e).is_none() => {
+                    debug!("Failed to decompress the truncated file {}: {:#}", input, e);
+                    (None, Vec::new())
+                }
+                Err(e) => return Err(e),
+            };
+
+            match source {
+                Some(source) => (source, compression_layers),
+                None => (Source::FilePath(target_file_path), compression_layers),
+            }
+        }
+        Source::Stdin => {
+            info!("Reading from STDIN...");
+            input = "STDIN".to_string();
+            Source::read_from_stdin(invoke_options, temp_dir)?
+        }
+        Source::TempFile(_) => unreachable!(),
+        Source::Memory(_) => unreachable!(),
+    };
+
+    let mut module_result = determine_source(
+        input,
+        target_source,
+        compression_layers,
+        config,
+        temp_dir,
+        invoke_options,
+    )?;
+    if let Some(reason) = truncation_reason {
+        module_result.set_truncated(reason);
+    }
+    store_cached_result(cache, content_key.as_deref(), &module_result);
+    Ok(module_result)
+}
+
+// Check the end of the local file for truncation if it is in BGZF. A failure to read the end is only warned about, as the format is determined regardless.
+fn check_truncation(path: &Path, input: &str) -> Option<String> {
+    truncation::check_file(path).unwrap_or_else(|e| {
+        warn!("Failed to check whether {} is truncated: {:#}", input, e);
+        None
+    })
+}
+
+// Check the end of the file being downloaded for truncation if it is in BGZF, with a request for the end unless the whole file has been downloaded.
+fn check_download_truncation(
+    header: &[u8],
+    download: &mut Download,
+    input: &str,
+) -> Option<String> {
+    if !truncation::is_bgzf(header) {
+        return None;
+    }
+    match download.read_tail(truncation::TAIL_LENGTH) {
+        Ok(Some(tail)) => truncation::check_tail(&tail),
+        Ok(None) => {
+            debug!(
+                "Not checking whether {} is truncated, as the server cannot send only the end of it",
+                input
+            );
+            None
+        }
+        Err(e) => {
+            warn!("Failed to check whether {} is truncated: {:#}", input, e);
+            None
+        }
+    }
+}
+
+// The result cached for the content by an earlier run, reported for this input.
+fn find_cached_result(cache: &Cache, content_key: &str, input: &str) -> Option<ModuleResult> {
+    let mut module_result = cache.find_result(content_key)?;
+    info!("Reusing the result cached for {}", input);
+    module_result.set_input(input.to_string());
+    Some(module_result)
+}
+
+// Cache the result to be reused by later runs. Only the results within the limits are cached, as the others depend on the time taken.
+fn store_cached_result(
+    cache: Option<&Cache>,
+    content_key: Option<&str>,
+    module_result: &ModuleResult,
+) {
+    let (Some(cache), Some(content_key)) = (cache, content_key) else {
+        return;
+    };
+    if module_result.status != InputStatus::Ok {
+        return;
+    }
+    if let Err(e) = cache.store_result(content_key, module_result) {
+        warn!(
+            "Failed to cache the result of {}: {:#}",
+            module_result.input, e
+        );
+    }
+}
+
+// The fingerprint of the configuration and the options which change the result, so that a cached result is only reused by the runs with the same ones.
+fn fingerprint(config: &Config, invoke_options: &InvokeOptions) -> Result<String> {
+    let mut fingerprint = format!(
+        "{}\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        serde_json::to_string(config)?
+    );
+    // the CWL documents can be modified without changing the configuration.
+    for module in config
+        .order
+        .iter()
+        .filter(|module| module.ends_with(".cwl"))
+    {
+        let document = std::fs::read(module).unwrap_or_default();
+        fingerprint.push_str(&crate::cache::sha256_hex(&document)?);
+        fingerprint.push('\n');
+    }
+    fingerprint.push_str(&format!(
+        "tidy={} no_decompress={} num_records={} all_matches={} decompress_depth={} limits={:?} reference={:?} strict={}",
+        invoke_options.tidy,
+        invoke_options.no_decompress,
+        invoke_options.num_records,
+        invoke_options.all_matches,
+        invoke_options.decompress_depth,
+        invoke_options.limits,
+        invoke_options.reference,
+        invoke_options.strict
+    ));
+    // the reference sequences of CRAM are also looked up in these directories and URLs.
+    for name in ["REF_CACHE", "REF_PATH"] {
+        fingerprint.push_str(&format!(" {}={:?}", name, std::env::var_os(name)));
+    }
+    crate::cache::sha256_hex(fingerprint.as_bytes())
+}
+
+// Determine the format of the input read into `target_source`, whose compression formats have been removed beforehand.
+fn determine_source(
+    input: String,
+    target_source: Source,
+    compression_layers: Vec<CompressedFormat>,
+    config: &Config,
+    temp_dir: &TempDir,
+    invoke_options: &InvokeOptions,
+) -> Result<ModuleResult> {
+    let mut module_result = run_modules(target_source, config, temp_dir, invoke_options)?;
+    report_compression_layers(&mut module_result, compression_layers);
+
+    module_result.set_input(input);
+    Ok(module_result)
+}
+
+// Report the outermost compression format as the format of the input, and the detected format as the decompressed one.
+// All the layers are reported as well if the input has been compressed more than once.
+fn report_compression_layers(
+    module_result: &mut ModuleResult,
+    compression_layers: Vec<CompressedFormat>,
+) {
+    // must swap the edam of the module result and the compressed format if decompress has been done.
+    if let Some(outermost) = compression_layers.first() {
+        module_result
+            .swap_edam_of_module_result_and_compressed_format(ModuleResult::from(outermost));
+    }
+    if compression_layers.len() > 1 {
+        module_result.compression_layers = compression_layers
+            .iter()
+            .map(CompressionLayer::from)
+            .collect();
+    }
+}
+
+// Determine the format of each file in the archive, and report them as the members of the archive.
+fn determine_archive(
+    archive_path: &Path,
+    archive_format: ArchiveFormat,
+    compressed_format: CompressedFormat,
+    config: &Config,
+    temp_dir: &TempDir,
+    invoke_options: &InvokeOptions,
+    cwl_module_exists: bool,
+) -> Result<ModuleResult> {
+    if cwl_module_exists && !invoke_options.tidy {
+        bail!("The `--tidy` options is required when using CWL modules with archives. If you want to treat the input file as is and not look into the archive, please use the `--no-decompress` option.");
+    }
+
+    let mut members = Vec::new();
+    for_each_member(
+        archive_path,
+        archive_format,
+        &compressed_format,
+        &Guard::new(invoke_options),
+        |name, reader| {
+            info!("Processing member: {}", name);
+            let (source, member_compression_layers) =
+                Source::read_from_reader(reader, invoke_options, temp_dir)?;
+            let mut member_result = run_modules(source, config, temp_dir, invoke_options)?;
+            report_compression_layers(&mut member_result, member_compression_layers);
+            member_result.set_input(name.to_string());
+            members.push(member_result);
+            Ok(())
+        },
+    )?;
+
+    let mut module_result = ModuleResult::from(&archive_format);
+    let compression_layers = match compressed_format {
+        CompressedFormat::None => Vec::new(),
+        compressed_format => vec![compressed_format],
+    };
+    report_compression_layers(&mut module_result, compression_layers);
+    module_result.set_members(members);
+    Ok(module_result)
 }
 
 fn run_modules(
@@ -442,6 +1652,13 @@ fn run_modules(
     temp_dir: &TempDir,
     invoke_options: &InvokeOptions,
 ) -> Result<ModuleResult> {
+    // CWL modules read the input from a file, so write the input into a tempfile if it has been kept in memory.
+    let target_source = if cwl_module_exists(config)? {
+        target_source.into_file_backed(temp_dir)?
+    } else {
+        target_source
+    };
+
     // Create an input file for CWL modules if there is any CWL module in the config file
     let cwl_input_file_path: Option<NamedTempFile> = if cwl_module_exists(config)? {
         let &target_file_path = &target_source.as_path().unwrap();
@@ -460,62 +1677,188 @@ fn run_modules(
         None
     };
 
-    let module_result = config
-        .order
-        .iter()
-        .find_map(|module| {
-            let module_path = Path::new(&module);
-            let module_extension = module_path
-                .extension()
-                .and_then(std::ffi::OsStr::to_str)
-                .unwrap_or("");
-
-            let result = match module_extension {
-                "" => crate::parser::invoke(module, &target_source, invoke_options),
-                "cwl" => {
-                    // CWL module invocation is skipped if the input is not a file path or URL.
-                    let target_file_path = target_source.as_path().unwrap();
-                    crate::ext_tools::invoke(
-                        module_path,
-                        target_file_path,
-                        cwl_input_file_path.as_ref().unwrap(),
-                        dummy_docker_path.as_ref().unwrap(),
-                        invoke_options,)
-                },
-                _ => Err(anyhow!(
-                    "An unsupported file extension '.{}' was specified for the module value in the conf file. Only .cwl is supported for external extension mode.",
-                    module_extension
-                )),
-            };
+    // invoke the built-in parsers concurrently beforehand if `--parallel-modules` is specified. Their results are used in the configured order below.
+    let mut invoked_in_parallel: HashMap<&String, Result<ModuleResult>> =
+        if invoke_options.parallel_modules {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = config
+                    .order
+                    .iter()
+                    .filter(|module| Path::new(module).extension().is_none())
+                    .map(|module| {
+                        let target_source = &target_source;
+                        let handle = scope.spawn(move || {
+                            crate::parser::invoke(module, target_source, invoke_options)
+                        });
+                        (module, handle)
+                    })
+                    .collect();
+
+                handles
+                    .into_iter()
+                    .map(|(module, handle)| {
+                        let module_result = handle
+                            .join()
+                            .unwrap_or_else(|_| Err(anyhow!("The module '{}' panicked", module)));
+                        (module, module_result)
+                    })
+                    .collect()
+            })
+        } else {
+            HashMap::new()
+        };
+
+    let mut invoke_module = |module: &String| {
+        if let Some(module_result) = invoked_in_parallel.remove(module) {
+            return module_result;
+        }
+
+        let module_path = Path::new(&module);
+        let module_extension = module_path
+            .extension()
+            .and_then(std::ffi::OsStr::to_str)
+            .unwrap_or("");
+
+        match module_extension {
+            "" => crate::parser::invoke(module, &target_source, invoke_options),
+            "cwl" => {
+                // CWL module invocation is skipped if the input is not a file path or URL.
+                let target_file_path = target_source.as_path().unwrap();
+                crate::ext_tools::invoke(
+                    module_path,
+                    target_file_path,
+                    cwl_input_file_path.as_ref().unwrap(),
+                    dummy_docker_path.as_ref().unwrap(),
+                    invoke_options,)
+            },
+            _ => Err(anyhow!(
+                "An unsupported file extension '.{}' was specified for the module value in the conf file. Only .cwl is supported for external extension mode.",
+                module_extension
+            )),
+        }
+    };
 
-            match result {
+    // invoke every module and keep the outcome of each of them if `--all-matches` is specified.
+    if invoke_options.all_matches {
+        let mut candidates: Vec<Candidate> = Vec::new();
+        let mut errors: Vec<ModuleError> = Vec::new();
+        for module in &config.order {
+            let candidate = match invoke_module(module) {
                 Ok(module_result) => {
                     if module_result.is_ok {
                         info!("Detected!! {}", module);
-                        Some(module_result)
                     } else {
-                        debug!(
-                            "Module \"{}\" failed. Reason:\n{}",
-                            module,
-                            module_result.error_message.unwrap_or_else(|| "".to_string())
-                        );
-                        None
+                        log_module_failure(module, &module_result);
+                        errors.push(ModuleError::new(module, &module_result));
                     }
-                },
+                    Candidate::new(module, module_result)
+                }
                 Err(e) => {
-                    // TODO fix an issue that a error here is absorbed by the find_map function.
-                    warn!("An error occurred while trying to invoke the \'{}\' module. Reason:\n{}", module, e);
+                    let module_result = failed_invocation(module, &e);
+                    errors.push(ModuleError::new(module, &module_result));
+                    Candidate::new(module, module_result)
+                }
+            };
+
+            // most of the parsers accept an empty input, so there is no point in invoking the rest of them.
+            let is_empty = module == "empty" && candidate.is_ok;
+            candidates.push(candidate);
+            if is_empty {
+                break;
+            }
+        }
+
+        // prefer the candidate with the highest confidence score. If tied, the one that comes first in the config wins.
+        let best_candidate = candidates.iter().filter(|c| c.is_ok).fold(
+            None,
+            |best: Option<&Candidate>, candidate| match best {
+                Some(best)
+                    if best.confidence.unwrap_or(0.0) >= candidate.confidence.unwrap_or(0.0) =>
+                {
+                    Some(best)
+                }
+                _ => Some(candidate),
+            },
+        );
+
+        let mut module_result = best_candidate.map_or_else(
+            || ModuleResult::with_result(None, None),
+            |c| {
+                let mut module_result = ModuleResult::with_result(c.label.clone(), c.id.clone());
+                module_result.version = c.version.clone();
+                module_result.details = c.details.clone();
+                module_result.confidence = c.confidence;
+                module_result
+            },
+        );
+        module_result.set_candidates(candidates);
+        if !module_result.is_format_detected() {
+            module_result.errors = errors;
+        }
+
+        return Ok(module_result);
+    }
+
+    let mut errors: Vec<ModuleError> = Vec::new();
+    let module_result = config
+        .order
+        .iter()
+        .find_map(|module| match invoke_module(module) {
+            Ok(module_result) => {
+                if module_result.is_ok {
+                    info!("Detected!! {}", module);
+                    Some(module_result)
+                } else {
+                    log_module_failure(module, &module_result);
+                    errors.push(ModuleError::new(module, &module_result));
                     None
-                },
+                }
+            }
+            Err(e) => {
+                errors.push(ModuleError::new(module, &failed_invocation(module, &e)));
+                None
             }
         })
-        .unwrap_or_else(|| {ModuleResult::with_result(None, None)});
+        .unwrap_or_else(|| {
+            let mut module_result = ModuleResult::with_result(None, None);
+            module_result.errors = errors;
+            module_result
+        });
 
     // std::thread::sleep(std::time::Duration::from_secs(50));
 
     Ok(module_result)
 }
 
+// The result of a module which could not be invoked, such as a CWL module failing to run, reported along with the failures of the other modules.
+fn failed_invocation(module: &str, e: &anyhow::Error) -> ModuleResult {
+    warn!(
+        "An error occurred while trying to invoke the \'{}\' module. Reason:\n{}",
+        module, e
+    );
+    let mut module_result = ModuleResult::with_result(None, None);
+    module_result.set_is_ok(false);
+    module_result.set_error_message(e.to_string());
+    module_result
+}
+
+// Log why a module failed, and where in the input it stopped, if the parser has located it.
+fn log_module_failure(module: &str, module_result: &ModuleResult) {
+    match &module_result.error_location {
+        Some(location) => debug!(
+            "Module \"{}\" failed at {}. Reason:\n{}",
+            module,
+            location,
+            module_result.error_message().map_or("", |m| m.as_str())
+        ),
+        None => debug!(
+            "Module \"{}\" failed. Reason:\n{}",
+            module,
+            module_result.error_message().map_or("", |m| m.as_str())
+        ),
+    }
+}
+
 pub fn dry_run(config: Config) -> Result<()> {
     // output the configuration file in yaml format
     let yaml = serde_yaml::to_string(&config)?;
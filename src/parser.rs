@@ -7,30 +7,56 @@ mod fasta;
 mod fastq;
 mod gff3;
 mod gtf;
+mod location;
 mod sam;
 mod template;
 mod vcf;
 
 use anyhow::{bail, Result};
 use log::info;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-use crate::module::{InvokeOptions, ModuleResult};
+pub use location::Location;
+use location::RecordError;
+
+use crate::limits::DeadlineReader;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
 use crate::source::Source;
 
 pub trait Parser {
-    /// Determines whether the provided file is in a format that this parser can interpret.
-    /// If the parser successfully interprets the file, it returns `Ok(ModuleResult)`.
-    /// Otherwise, it returns `Err(anyhow::Error)`, with an error message explaining why the parser cannot process the file.
+    /// Determines whether the content read from `reader` is in a format that this parser can interpret.
+    /// If the parser successfully interprets the content, it returns `Ok(ModuleResult)`.
+    /// Otherwise, it returns `Err(anyhow::Error)`, with an error message explaining why the parser cannot process the content.
     /// To construct a `ModuleResult`, use `ModuleResult::with_result()`, which requires `label` and `id` as parameters.
+    /// Attach the `Evidence` collected while reading the input with `ModuleResult::with_evidence()`.
     ///
     /// - `id`: EDAM Class ID
     /// - `label`: EDAM Preferred Label
+    fn determine_from_reader(
+        &self,
+        reader: &mut dyn BufRead,
+        options: &InvokeOptions,
+    ) -> Result<ModuleResult>;
+
+    /// Determines whether the provided file is in a format that this parser can interpret.
+    /// Override this method only if the file can be checked without reading it, as the default implementation reads it with `determine_from_reader`.
     fn determine_from_path(
         &self,
         input_path: &Path,
         options: &InvokeOptions,
-    ) -> Result<ModuleResult>;
+    ) -> Result<ModuleResult> {
+        let file = File::open(input_path)?;
+        let mut reader = BufReader::new(DeadlineReader::new(file, options.deadline));
+        self.determine_from_reader(&mut reader, options)
+    }
+
+    /// Computes the confidence score of a successful detection, ranging from 0.0 to 1.0, from the `Evidence` attached to the `ModuleResult`.
+    /// Override this method if the evidence needs to be weighted differently for the format.
+    fn confidence(&self, evidence: &Evidence) -> f64 {
+        evidence.score()
+    }
 }
 
 pub fn from_str_to_parser(module_name: &str) -> Result<Box<dyn Parser>> {
@@ -63,26 +89,48 @@ pub fn invoke(
 
     let parser = from_str_to_parser(module_name)?;
 
-    // Convert Source to readable object
-    let target_file_path = match target_source {
-        Source::FilePath(target_file_path) => target_file_path,
-        Source::TempFile(target_temp_file) => target_temp_file.path(),
-        Source::Stdin => {
-            unreachable!()
+    // Read the input from the file, or from the buffered bytes if it has been kept in memory.
+    let determined = match target_source {
+        Source::FilePath(target_file_path) => parser.determine_from_path(target_file_path, options),
+        Source::TempFile(target_temp_file) => {
+            parser.determine_from_path(target_temp_file.path(), options)
         }
-        Source::Memory(_) => {
+        Source::Memory(bytes) => parser.determine_from_reader(
+            &mut BufReader::new(DeadlineReader::new(bytes.as_slice(), options.deadline)),
+            options,
+        ),
+        // STDIN is read into a temporary file or memory beforehand, as it cannot be read by more than one parser.
+        Source::Stdin => {
             unreachable!()
         }
     };
 
-    Ok(parser
-        .determine_from_path(target_file_path, options)
-        .unwrap_or_else(|e| {
+    // parsers may turn the error of the reader into their own, so the deadline is checked again to report the timeout of the input instead of the module failure.
+    if determined.is_err() {
+        if let Some(deadline) = &options.deadline {
+            deadline.check()?;
+        }
+    }
+
+    Ok(determined.map_or_else(
+        |e| {
             let mut module_result = ModuleResult::with_result(None, None);
             module_result.set_is_ok(false);
-            module_result.set_error_message(e.to_string());
+            match e.downcast::<RecordError>() {
+                Ok(record_error) => {
+                    module_result.set_error_message(record_error.message());
+                    module_result.set_error_location(record_error.location);
+                }
+                Err(e) => module_result.set_error_message(e.to_string()),
+            }
             module_result
-        }))
+        },
+        |mut module_result| {
+            let evidence = module_result.evidence().copied().unwrap_or_default();
+            module_result.set_confidence(parser.confidence(&evidence));
+            module_result
+        },
+    ))
 }
 
 #[cfg(test)]
@@ -90,6 +138,23 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    /// The options the parsers are invoked with in the tests, reading the whole input.
+    pub(crate) fn options() -> InvokeOptions {
+        InvokeOptions {
+            tidy: true,
+            no_decompress: false,
+            num_records: 100000,
+            all_matches: false,
+            parallel_modules: false,
+            decompress_depth: 3,
+            limits: Default::default(),
+            deadline: None,
+            fetch: Default::default(),
+            reference: None,
+            strict: false,
+        }
+    }
+
     /// Tests the result of invoking the parser when the `determine` (in this case `determine_from_path`) function successfully identifies the file format.
     /// This function verifies that the returned `ModuleResult` contains the expected `label` and `id`.`
     fn invoke_wrapper_determine_pass(
@@ -99,11 +164,7 @@ mod tests {
         id: &str,
     ) {
         let target_source = Source::FilePath(target_file_path.to_path_buf());
-        let options = InvokeOptions {
-            tidy: true,
-            no_decompress: false,
-            num_records: 100000,
-        };
+        let options = options();
         let result = invoke(module_name, &target_source, &options).unwrap();
 
         assert_eq!(result.label(), Some(&label.to_string()));
@@ -118,11 +179,7 @@ mod tests {
         error_message: &str,
     ) {
         let target_source = Source::FilePath(target_file_path.to_path_buf());
-        let options = InvokeOptions {
-            tidy: true,
-            no_decompress: false,
-            num_records: 100000,
-        };
+        let options = options();
         let result = invoke(module_name, &target_source, &options).unwrap();
 
         assert_eq!(result.error_message(), Some(&error_message.to_string()));
@@ -143,6 +200,86 @@ mod tests {
         invoke_wrapper_determine_fail("empty", &not_empty_input_path, "The file is not empty");
     }
 
+    #[test]
+    fn test_confidence_invoke() {
+        let options = options();
+        let confidence_of = |module_name: &str, path: &str| {
+            let target_source = Source::FilePath(PathBuf::from(path));
+            invoke(module_name, &target_source, &options)
+                .unwrap()
+                .confidence()
+                .unwrap()
+        };
+
+        // an empty file is always detected with full confidence.
+        assert_eq!(confidence_of("empty", "./tests/inputs/empty"), 1.0);
+
+        // magic bytes and a validated header raise the confidence of BAM above SAM with the same records.
+        assert!(
+            confidence_of("bam", "./tests/inputs/toy.bam")
+                > confidence_of("sam", "./tests/inputs/toy.sam")
+        );
+
+        // a file with only header lines has no records to back up the detection.
+        assert_eq!(
+            confidence_of("bed", "./tests/inputs/toy_header_only.txt"),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_error_location_invoke() {
+        let options = options();
+        let target_source = Source::FilePath(PathBuf::from("./tests/inputs/toy_invalid_flag.sam"));
+        let result = invoke("sam", &target_source, &options).unwrap();
+
+        // the message is kept apart from where the parser stopped.
+        assert_eq!(result.error_message(), Some(&"invalid flags".to_string()));
+        let location = result.error_location().unwrap();
+        assert_eq!(location.record, Some(2));
+        assert_eq!(location.line, Some(4));
+        assert_eq!(location.byte_offset, Some(116));
+        assert!(location
+            .excerpt
+            .as_ref()
+            .unwrap()
+            .starts_with("r002\tA\tref"));
+
+        // a record which spans several lines is located at the line being read.
+        let target_source = Source::FilePath(PathBuf::from("./tests/inputs/toy.sam"));
+        let result = invoke("fastq", &target_source, &options).unwrap();
+        let location = result.error_location().unwrap();
+        assert_eq!(location.record, Some(1));
+        assert!(location.line > Some(1));
+    }
+
+    #[test]
+    fn test_memory_invoke() {
+        let options = options();
+
+        // inputs kept in memory are parsed in the same way as files.
+        for (module_name, path, label) in [
+            ("sam", "./tests/inputs/toy.sam", "SAM"),
+            ("bam", "./tests/inputs/toy.bam", "BAM"),
+            ("fastq", "./tests/inputs/toy.fq", "FASTQ"),
+        ] {
+            let target_source = Source::Memory(std::fs::read(path).unwrap());
+            let result = invoke(module_name, &target_source, &options).unwrap();
+            assert_eq!(result.label(), Some(&label.to_string()));
+        }
+
+        let target_source = Source::Memory(Vec::new());
+        let result = invoke("empty", &target_source, &options).unwrap();
+        assert_eq!(
+            result.label(),
+            Some(&"plain text format (unformatted)".to_string())
+        );
+
+        let target_source = Source::Memory(std::fs::read("./tests/inputs/toy.fa").unwrap());
+        let result = invoke("sam", &target_source, &options).unwrap();
+        assert_eq!(result.error_message(), Some(&"invalid flags".to_string()));
+    }
+
     #[test]
     fn test_bam_invoke() {
         let bam_input_path = PathBuf::from("./tests/inputs/toy.bam");
@@ -192,7 +329,6 @@ mod tests {
     fn test_cram_invoke() {
         let cram_input_path = PathBuf::from("./tests/inputs/toy.cram");
 
-        // TODO: review this later. currently the assert fails due to the CRAM file not being valid.
         invoke_wrapper_determine_pass(
             "cram",
             &cram_input_path,
@@ -1,31 +1,61 @@
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::location::{Location, RecordError};
+use crate::parser::sam::validation::Validator;
+use crate::parser::sam::with_header_details;
 use crate::parser::Parser;
 
 pub struct Bam;
 
 impl Parser for Bam {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::bam::reader::Builder.build_from_path(input_path)?;
+        let mut reader = noodles::bam::Reader::new(reader);
         let header = reader.read_header()?;
 
-        for (count, result) in reader.records(&header).enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+        // the records are also checked against the header and against each other with `--strict`.
+        let mut validator = options.strict.then(|| Validator::new(&header));
+        let mut record = noodles::sam::alignment::Record::default();
+        let mut num_records_read = 0;
+        loop {
+            let position = reader.virtual_position();
+            match reader.read_record(&header, &mut record) {
+                Ok(0) => break,
+                Ok(_) => num_records_read += 1,
+                Err(e) => {
+                    return Err(
+                        RecordError::at_virtual_position(num_records_read + 1, position, e).into(),
+                    )
+                }
+            }
+            if let Some(validator) = validator.as_mut() {
+                validator.check(&record, || {
+                    Location::at_virtual_position(num_records_read, position)
+                });
+            }
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
-            if !options.tidy && count + 2 > options.num_records {
+            if !options.tidy && num_records_read + 1 > options.num_records {
                 break;
             }
         }
-        Ok(ModuleResult::with_result(
+        if let Some(validator) = validator {
+            validator.finish()?;
+        }
+
+        let module_result = ModuleResult::with_result(
             Some("BAM".to_string()),
             Some("http://edamontology.org/format_2572".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: true,
+            magic: true,
+        });
+        Ok(with_header_details(module_result, &header))
     }
 }
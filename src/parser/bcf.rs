@@ -1,33 +1,79 @@
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::location::{Location, RecordError};
+use crate::parser::vcf::validation::Validator;
 use crate::parser::Parser;
 
 pub struct Bcf;
 
 impl Parser for Bcf {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::bcf::reader::Builder::default().build_from_path(input_path)?;
+        // the version of BCF following the magic number is peeked at, as the reader discards it.
+        let mut inner = noodles::bgzf::Reader::new(reader);
+        let bcf_version = match inner.fill_buf()? {
+            [b'B', b'C', b'F', major, minor, ..] => Some(format!("{}.{}", major, minor)),
+            _ => None,
+        };
+        let mut reader = noodles::bcf::Reader::from(inner);
 
         let header = reader.read_header()?;
+        // the records are also checked against the header with `--strict`.
+        let mut validator = options.strict.then(|| Validator::new(&header));
 
-        for (count, result) in reader.records(&header).enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+        let mut record = noodles::vcf::Record::default();
+        let mut num_records_read = 0;
+        loop {
+            let position = reader.virtual_position();
+            match reader.read_record(&header, &mut record) {
+                Ok(0) => break,
+                Ok(_) => {
+                    num_records_read += 1;
+                    if let Some(validator) = validator.as_mut() {
+                        // the samples are decoded as many as the record tells, whatever the header declares.
+                        let num_samples = record.genotypes().values().count();
+                        validator.check(&record, num_samples, || {
+                            Location::at_virtual_position(num_records_read, position)
+                        });
+                    }
+                }
+                Err(e) => {
+                    return Err(
+                        RecordError::at_virtual_position(num_records_read + 1, position, e).into(),
+                    )
+                }
+            }
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
-            if !options.tidy && count + 2 > options.num_records {
+            if !options.tidy && num_records_read + 1 > options.num_records {
                 break;
             }
         }
+        if let Some(validator) = validator {
+            validator.finish()?;
+        }
 
-        Ok(ModuleResult::with_result(
+        let file_format = header.file_format();
+        let module_result = ModuleResult::with_result(
             Some("BCF".to_string()),
             Some("http://edamontology.org/format_3020".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: true,
+            magic: true,
+        })
+        .with_detail(
+            "vcf_version",
+            format!("{}.{}", file_format.major(), file_format.minor()),
+        );
+        Ok(match bcf_version {
+            Some(version) => module_result.with_version(version),
+            None => module_result,
+        })
     }
 }
@@ -1,25 +1,26 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 pub struct Bed;
 
 impl Parser for Bed {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = File::open(input_path)
-            .map(BufReader::new)
-            .map(noodles::bed::Reader::new)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::bed::Reader::new(inner);
 
+        let mut num_records_read = 0;
+        // the largest number of columns among the records, such as 6 for BED6.
+        let mut num_columns = 0;
         for (count, result) in reader.records::<3>().enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+            let record = result.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
+            num_columns = num_columns.max(3 + record.optional_fields().len());
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
@@ -27,9 +28,19 @@ impl Parser for Bed {
             }
         }
 
-        Ok(ModuleResult::with_result(
+        let module_result = ModuleResult::with_result(
             Some("BED".to_string()),
             Some("http://edamontology.org/format_3003".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: false,
+            magic: false,
+        });
+        Ok(if num_records_read > 0 {
+            module_result.with_detail("columns", num_columns)
+        } else {
+            module_result
+        })
     }
 }
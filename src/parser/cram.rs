@@ -1,38 +1,130 @@
-use std::path::Path;
+mod reference;
 
-use crate::module::{InvokeOptions, ModuleResult};
+use log::{debug, warn};
+use noodles::cram::data_container::{CompressionHeader, Slice};
+use noodles::{fasta, sam};
+use std::collections::HashSet;
+use std::io::{self, BufRead};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::location::{self, RecordError};
 use crate::parser::Parser;
+use reference::{MissingReference, ReferenceMismatch, References};
 
 pub struct Cram;
 
 impl Parser for Cram {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
-        #[allow(unused_variables)] options: &InvokeOptions,
+        reader: &mut dyn BufRead,
+        options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::cram::reader::Builder::default().build_from_path(input_path)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::cram::Reader::new(inner);
 
         // Check for the CRAM magic number and read its SAM header.
-        #[allow(unused_variables)]
-        let header = reader.read_header()?;
-
-        // TODO: need reference sequence handling
-        /*
-        for (count, result) in reader.records(&header).enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
-
-            // If the tidy option is not set, the number of lines to read is limited to num_records.
-            if !options.tidy && count + 2 > options.num_records {
-                break;
+        let file_definition = reader.read_file_definition()?;
+        let header = reader.read_file_header()?;
+        let repository = fasta::Repository::new(References::new(&header, options)?);
+
+        // The records are decoded a container at a time, and the slices in it at a time.
+        let mut num_records_read = 0;
+        let mut missing_references = HashSet::new();
+        'containers: loop {
+            let offset = tracker.byte_offset();
+            let container = match reader.read_data_container() {
+                Ok(Some(container)) => container,
+                Ok(None) => break,
+                Err(e) => {
+                    return Err(RecordError::at_byte_offset(num_records_read + 1, offset, e).into())
+                }
+            };
+            for slice in container.slices() {
+                let num_records = decode_slice(
+                    slice,
+                    container.compression_header(),
+                    &header,
+                    &repository,
+                    &mut missing_references,
+                )
+                .map_err(|e| RecordError::at_byte_offset(num_records_read + 1, offset, e))?;
+                num_records_read += num_records;
+
+                // If the tidy option is not set, the number of records to read is limited to num_records.
+                if !options.tidy && num_records_read >= options.num_records {
+                    num_records_read = options.num_records;
+                    break 'containers;
+                }
             }
         }
-        */
 
-        Ok(ModuleResult::with_result(
+        let version = file_definition.version();
+        let mut module_result = ModuleResult::with_result(
             Some("CRAM".to_string()),
             Some("http://edamontology.org/format_3462".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: true,
+            magic: true,
+        })
+        .with_version(format!("{}.{}", version.major(), version.minor()));
+        if let Some(sort_order) = header.header().and_then(|map| map.sort_order()) {
+            module_result = module_result.with_detail("sort_order", sort_order.to_string());
+        }
+        Ok(module_result)
     }
 }
+
+// Decode the records in the slice and resolve their bases against the reference sequence, returning the number of the records.
+// The records referring to a reference sequence not found are still decoded, with a warning that their bases are not checked.
+fn decode_slice(
+    slice: &Slice,
+    compression_header: &CompressionHeader,
+    header: &sam::Header,
+    repository: &fasta::Repository,
+    missing_references: &mut HashSet<String>,
+) -> io::Result<usize> {
+    // noodles panics on some inconsistencies in a corrupted slice, such as a reference sequence ID out of the header, instead of returning an error.
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut records = slice.records(compression_header)?;
+        if let Err(e) = slice.resolve_records(repository, header, compression_header, &mut records)
+        {
+            if let Some(missing) = e
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<MissingReference>())
+            {
+                // the warning is given once for an input, however many sequences are missing.
+                if missing_references.insert(missing.name.clone()) {
+                    if missing_references.len() == 1 {
+                        warn!(
+                            "{}, so the bases of the CRAM records are not checked against the reference. Give the FASTA file with --reference.",
+                            missing
+                        );
+                    } else {
+                        debug!("{}", missing);
+                    }
+                }
+            } else if let Some(mismatch) = e
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<ReferenceMismatch>())
+            {
+                // the reference sequence has been checked against the header before the records are resolved against it.
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    mismatch.to_string(),
+                ));
+            } else {
+                return Err(e);
+            }
+        }
+        Ok(records.len())
+    }))
+    .unwrap_or_else(|_| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Failed to decode the CRAM records in a corrupted slice",
+        ))
+    })
+}
@@ -0,0 +1,421 @@
+use anyhow::{Context, Result};
+use log::debug;
+use noodles::fasta::{self, fai, repository::Adapter};
+use noodles::sam;
+use openssl::hash::{hash, MessageDigest};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use url::Url;
+
+use crate::fetch::{open_remote, Client, HttpSettings};
+use crate::limits::{Deadline, DeadlineReader};
+use crate::module::InvokeOptions;
+
+/// Environment variable giving where to look up the reference sequences by their MD5, the same as samtools.
+const REF_PATH_ENV: &str = "REF_PATH";
+
+/// Environment variable giving where to keep the reference sequences found in REF_PATH, the same as samtools.
+const REF_CACHE_ENV: &str = "REF_CACHE";
+
+// The error returned for a reference sequence found in none of `--reference`, REF_CACHE and REF_PATH.
+#[derive(Debug)]
+pub struct MissingReference {
+    pub name: String,
+}
+
+impl fmt::Display for MissingReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No reference sequence is found for {} in --reference, REF_CACHE or REF_PATH",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for MissingReference {}
+
+// The error returned for a reference sequence not matching `M5` in the header, or `LN` if `M5` is not given.
+#[derive(Debug)]
+pub enum ReferenceMismatch {
+    Md5 {
+        name: String,
+        expected: String,
+        actual: String,
+        source: String,
+    },
+    Length {
+        name: String,
+        expected: usize,
+        actual: usize,
+        source: String,
+    },
+}
+
+impl fmt::Display for ReferenceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Md5 {
+                name,
+                expected,
+                actual,
+                source,
+            } => write!(
+                f,
+                "Reference MD5 mismatch for {}: @SQ M5 is {} but the sequence in {} has {}",
+                name, expected, source, actual
+            ),
+            Self::Length {
+                name,
+                expected,
+                actual,
+                source,
+            } => write!(
+                f,
+                "Reference length mismatch for {}: @SQ LN is {} but the sequence in {} has {} bases",
+                name, expected, source, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReferenceMismatch {}
+
+// The reference sequences of a CRAM file, looked up by their names in `--reference`, and then by their MD5 in `@SQ M5` in REF_CACHE and REF_PATH.
+// The sequences are checked against `M5` in the header, or against `LN` if `M5` is not given.
+pub struct References {
+    fasta: Option<Fasta>,
+    // `LN` and `M5` of the reference sequences in the header
+    reference_sequences: HashMap<String, (usize, Option<String>)>,
+    ref_cache: Option<String>,
+    ref_path: Vec<String>,
+    http: Arc<HttpSettings>,
+    deadline: Option<Deadline>,
+}
+
+impl References {
+    pub fn new(header: &sam::Header, options: &InvokeOptions) -> Result<Self> {
+        let reference_sequences = header
+            .reference_sequences()
+            .iter()
+            .map(|(name, reference_sequence)| {
+                (
+                    name.to_string(),
+                    (
+                        usize::from(reference_sequence.length()),
+                        reference_sequence.md5_checksum().map(|md5| md5.to_string()),
+                    ),
+                )
+            })
+            .collect();
+        Ok(Self {
+            fasta: options.reference.as_deref().map(Fasta::open).transpose()?,
+            reference_sequences,
+            ref_cache: std::env::var(REF_CACHE_ENV)
+                .ok()
+                .filter(|ref_cache| !ref_cache.is_empty()),
+            ref_path: std::env::var(REF_PATH_ENV)
+                .map(|ref_path| split_ref_path(&ref_path))
+                .unwrap_or_default(),
+            http: options.fetch.http.clone(),
+            deadline: options.deadline,
+        })
+    }
+
+    fn find(&mut self, name: &str) -> io::Result<(Vec<u8>, String)> {
+        if let Some(fasta) = self.fasta.as_mut() {
+            let names = &self.reference_sequences;
+            if let Some(sequence) = fasta.get(name, |name| names.contains_key(name))? {
+                return Ok((sequence, format!("--reference {}", fasta.path.display())));
+            }
+        }
+        if let Some(md5) = self
+            .reference_sequences
+            .get(name)
+            .and_then(|(_, md5)| md5.clone())
+        {
+            if let Some(found) = self.find_by_md5(&md5) {
+                return Ok(found);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            MissingReference {
+                name: name.to_string(),
+            },
+        ))
+    }
+
+    // Look up the sequence in REF_CACHE, and then in REF_PATH. The sequences downloaded from REF_PATH are kept in REF_CACHE.
+    // The sequences failing to be read, or not matching the MD5, are skipped as if they were not there.
+    fn find_by_md5(&self, md5: &str) -> Option<(Vec<u8>, String)> {
+        if let Some(ref_cache) = &self.ref_cache {
+            let path = expand_template(ref_cache, md5);
+            if let Some(sequence) = read_local(Path::new(&path), md5) {
+                return Some((sequence, format!("REF_CACHE {}", path)));
+            }
+        }
+        for template in &self.ref_path {
+            let location = expand_template(template, md5);
+            // a Windows path such as `C:\ref\%s` is parsed as a URL with a single letter scheme.
+            let url = Url::parse(&location)
+                .ok()
+                .filter(|url| url.scheme().len() > 1);
+            let Some(url) = url.filter(|url| url.scheme() != "file") else {
+                let path = Url::parse(&location)
+                    .ok()
+                    .and_then(|url| url.to_file_path().ok())
+                    .unwrap_or_else(|| PathBuf::from(&location));
+                if let Some(sequence) = read_local(&path, md5) {
+                    return Some((sequence, format!("REF_PATH {}", location)));
+                }
+                continue;
+            };
+            let sequence = match self.download(&url) {
+                Ok(sequence) if md5_hex(&sequence) == md5 => sequence,
+                Ok(_) => {
+                    debug!("The sequence at {} does not match its MD5", url);
+                    continue;
+                }
+                Err(e) => {
+                    debug!("Failed to download the reference sequence {}: {:#}", url, e);
+                    continue;
+                }
+            };
+            if let Some(ref_cache) = &self.ref_cache {
+                if let Err(e) = store(Path::new(&expand_template(ref_cache, md5)), &sequence) {
+                    debug!("Failed to keep {} in REF_CACHE: {:#}", url, e);
+                }
+            }
+            return Some((sequence, format!("REF_PATH {}", url)));
+        }
+        None
+    }
+
+    fn download(&self, url: &Url) -> Result<Vec<u8>> {
+        let timeout = self.deadline.map_or(self.http.timeout, |deadline| {
+            deadline.remaining().min(self.http.timeout)
+        });
+        let client = Client::new(&self.http, timeout)?;
+        let remote = open_remote(url, &client, 0)?;
+        let mut content = Vec::new();
+        DeadlineReader::new(remote.reader, self.deadline).read_to_end(&mut content)?;
+        Ok(normalize(&content))
+    }
+
+    // The sequence must have the MD5 in `M5`, or the length in `LN` if `M5` is not given.
+    fn check(&self, name: &str, sequence: &[u8], source: &str) -> io::Result<()> {
+        let Some((length, md5)) = self.reference_sequences.get(name) else {
+            return Ok(());
+        };
+        let mismatch = match md5 {
+            Some(md5) => {
+                let actual = md5_hex(sequence);
+                (actual != *md5).then(|| ReferenceMismatch::Md5 {
+                    name: name.to_string(),
+                    expected: md5.clone(),
+                    actual,
+                    source: source.to_string(),
+                })
+            }
+            None => (sequence.len() != *length).then(|| ReferenceMismatch::Length {
+                name: name.to_string(),
+                expected: *length,
+                actual: sequence.len(),
+                source: source.to_string(),
+            }),
+        };
+        match mismatch {
+            Some(mismatch) => Err(io::Error::new(io::ErrorKind::InvalidData, mismatch)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Adapter for References {
+    // A sequence not found is returned as an error rather than `None`, which noodles does not expect for the sequences that the records refer to.
+    fn get(&mut self, name: &str) -> Option<io::Result<fasta::Record>> {
+        Some(self.find(name).and_then(|(sequence, source)| {
+            self.check(name, &sequence, &source)?;
+            Ok(fasta::Record::new(
+                fasta::record::Definition::new(name, None),
+                fasta::record::Sequence::from(sequence),
+            ))
+        }))
+    }
+}
+
+// The FASTA file given by `--reference`, read through the `.fai` index if it exists, and loaded into memory otherwise.
+struct Fasta {
+    path: PathBuf,
+    source: FastaSource,
+}
+
+enum FastaSource {
+    Indexed(fasta::IndexedReader<Box<dyn fasta::io::BufReadSeek>>),
+    // loaded on the first lookup
+    Unindexed(Option<HashMap<String, Vec<u8>>>),
+}
+
+impl Fasta {
+    fn open(path: &Path) -> Result<Self> {
+        let mut index_path = path.as_os_str().to_owned();
+        index_path.push(".fai");
+        let source = if Path::new(&index_path).exists() {
+            let index = fai::read(&index_path).with_context(|| {
+                format!(
+                    "Failed to read the index of the reference: {}",
+                    Path::new(&index_path).display()
+                )
+            })?;
+            let reader = fasta::indexed_reader::Builder::default()
+                .set_index(index)
+                .build_from_path(path)
+                .with_context(|| format!("Failed to open the reference: {}", path.display()))?;
+            FastaSource::Indexed(reader)
+        } else {
+            if !path.is_file() {
+                anyhow::bail!("The reference is not found: {}", path.display());
+            }
+            FastaSource::Unindexed(None)
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    // The sequence of the name, if the FASTA has it. Only the sequences `is_needed` are kept when the whole FASTA is read.
+    fn get(&mut self, name: &str, is_needed: impl Fn(&str) -> bool) -> io::Result<Option<Vec<u8>>> {
+        match &mut self.source {
+            FastaSource::Indexed(reader) => {
+                if !reader.index().iter().any(|record| record.name() == name) {
+                    return Ok(None);
+                }
+                let record = reader.query(&noodles::core::Region::new(name, ..))?;
+                Ok(Some(normalize(record.sequence().as_ref())))
+            }
+            FastaSource::Unindexed(sequences) => {
+                if sequences.is_none() {
+                    debug!(
+                        "Reading the whole reference {}, as it has no .fai index",
+                        self.path.display()
+                    );
+                    let mut reader = fasta::Reader::new(BufReader::new(File::open(&self.path)?));
+                    let mut loaded = HashMap::new();
+                    for result in reader.records() {
+                        let record = result?;
+                        if is_needed(record.name()) {
+                            loaded.insert(
+                                record.name().to_string(),
+                                normalize(record.sequence().as_ref()),
+                            );
+                        }
+                    }
+                    *sequences = Some(loaded);
+                }
+                Ok(sequences
+                    .as_mut()
+                    .and_then(|sequences| sequences.remove(name)))
+            }
+        }
+    }
+}
+
+// The sequence in upper case without the characters other than the printable ones, of which the MD5 in `M5` is calculated.
+fn normalize(sequence: &[u8]) -> Vec<u8> {
+    sequence
+        .iter()
+        .filter(|base| base.is_ascii_graphic())
+        .map(u8::to_ascii_uppercase)
+        .collect()
+}
+
+fn md5_hex(sequence: &[u8]) -> String {
+    hash(MessageDigest::md5(), sequence)
+        .map(|digest| crate::cache::to_hex(&digest))
+        .unwrap_or_default()
+}
+
+// The sequence in the file named by its MD5, such as `<REF_CACHE>/ab/cd/ef...`.
+fn read_local(path: &Path, md5: &str) -> Option<Vec<u8>> {
+    let sequence = normalize(&std::fs::read(path).ok()?);
+    if md5_hex(&sequence) != md5 {
+        debug!("The sequence in {} does not match its MD5", path.display());
+        return None;
+    }
+    Some(sequence)
+}
+
+fn store(path: &Path, sequence: &[u8]) -> Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+    let mut temp_file = tempfile::NamedTempFile::new_in(parent)?;
+    temp_file.write_all(sequence)?;
+    temp_file.persist(path)?;
+    Ok(())
+}
+
+// Replace `%<N>s` in the template with the next N characters of the MD5, and `%s` with the rest, as samtools does.
+// The template without `%s` is taken as a directory, in which the files are named by the MD5.
+fn expand_template(template: &str, md5: &str) -> String {
+    let template = if template.contains("%s") {
+        template.to_string()
+    } else {
+        format!("{}/%s", template.trim_end_matches('/'))
+    };
+    let mut expanded = String::new();
+    let mut rest_of_md5 = md5;
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        if chars.next_if_eq(&'s').is_some() {
+            let length = digits.parse().map_or(rest_of_md5.len(), |length: usize| {
+                length.min(rest_of_md5.len())
+            });
+            expanded.push_str(&rest_of_md5[..length]);
+            rest_of_md5 = &rest_of_md5[length..];
+        } else {
+            expanded.push('%');
+            expanded.push_str(&digits);
+        }
+    }
+    expanded
+}
+
+// Split REF_PATH by colons, except for the ones in URLs such as `https://example.org:8080/%s`, which follow the scheme or precede the port.
+fn split_ref_path(ref_path: &str) -> Vec<String> {
+    let mut templates: Vec<String> = Vec::new();
+    for part in ref_path.split(':') {
+        let is_in_url = templates.last().is_some_and(|last| {
+            let follows_scheme = part.starts_with("//")
+                && !last.is_empty()
+                && last.chars().all(|c| c.is_ascii_alphanumeric());
+            let precedes_port = last
+                .split_once("://")
+                .is_some_and(|(_, host)| !host.contains('/'))
+                && part.starts_with(|c: char| c.is_ascii_digit());
+            follows_scheme || precedes_port
+        });
+        match templates.last_mut() {
+            Some(last) if is_in_url => {
+                last.push(':');
+                last.push_str(part);
+            }
+            _ => templates.push(part.to_string()),
+        }
+    }
+    templates.retain(|template| !template.is_empty());
+    templates
+}
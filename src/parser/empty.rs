@@ -1,7 +1,8 @@
 use std::fs;
+use std::io::BufRead;
 use std::path::Path;
 
-use crate::module::{InvokeOptions, ModuleResult};
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
 use crate::parser::Parser;
 
 pub struct Empty;
@@ -23,4 +24,25 @@ impl Parser for Empty {
             anyhow::bail!("The file is not empty");
         }
     }
+
+    // check if the content is empty or not.
+    fn determine_from_reader(
+        &self,
+        reader: &mut dyn BufRead,
+        #[allow(unused_variables)] options: &InvokeOptions,
+    ) -> anyhow::Result<ModuleResult> {
+        if reader.fill_buf()?.is_empty() {
+            Ok(ModuleResult::with_result(
+                Some("plain text format (unformatted)".to_string()),
+                Some("http://edamontology.org/format_1964".to_string()),
+            ))
+        } else {
+            anyhow::bail!("The file is not empty");
+        }
+    }
+
+    // an empty file is always an empty file.
+    fn confidence(&self, #[allow(unused_variables)] evidence: &Evidence) -> f64 {
+        1.0
+    }
 }
@@ -1,21 +1,24 @@
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 pub struct Fasta;
 
 impl Parser for Fasta {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::fasta::reader::Builder.build_from_path(input_path)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::fasta::Reader::new(inner);
 
+        let mut num_records_read = 0;
         for (count, result) in reader.records().enumerate() {
             #[allow(unused_variables)]
-            let record = result?;
+            let record = result.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
@@ -26,6 +29,11 @@ impl Parser for Fasta {
         Ok(ModuleResult::with_result(
             Some("FASTA".to_string()),
             Some("http://edamontology.org/format_1929".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: false,
+            magic: false,
+        }))
     }
 }
@@ -1,25 +1,29 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 pub struct Fastq;
 
 impl Parser for Fastq {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = File::open(input_path)
-            .map(BufReader::new)
-            .map(noodles::fastq::Reader::new)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::fastq::Reader::new(inner);
 
+        let mut num_records_read = 0;
+        // the shortest and the longest reads, which tell fixed-length short reads from long reads.
+        let mut read_lengths: Option<(usize, usize)> = None;
         for (count, result) in reader.records().enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+            let record = result.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
+            let length = record.sequence().len();
+            read_lengths = Some(read_lengths.map_or((length, length), |(min, max)| {
+                (min.min(length), max.max(length))
+            }));
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
@@ -27,9 +31,20 @@ impl Parser for Fastq {
             }
         }
 
-        Ok(ModuleResult::with_result(
+        let module_result = ModuleResult::with_result(
             Some("FASTQ".to_string()),
             Some("http://edamontology.org/format_1930".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: false,
+            magic: false,
+        });
+        Ok(match read_lengths {
+            Some((min, max)) => module_result
+                .with_detail("min_read_length", min)
+                .with_detail("max_read_length", max),
+            None => module_result,
+        })
     }
 }
@@ -1,35 +1,52 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use noodles::gff::{Directive, Line};
+
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 pub struct Gff3;
 
 impl Parser for Gff3 {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = File::open(input_path)
-            .map(BufReader::new)
-            .map(noodles::gff::Reader::new)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::gff::Reader::new(inner);
 
-        for (count, result) in reader.records().enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+        let mut num_records_read = 0;
+        let mut gff_version = None;
+        for result in reader.lines() {
+            // Directives and comments are not counted as records. Records end at the FASTA directive.
+            match result.map_err(|e| tracker.in_record(num_records_read + 1, e))? {
+                Line::Directive(Directive::GffVersion(version)) => gff_version = Some(version),
+                Line::Directive(Directive::StartOfFasta) => break,
+                Line::Directive(_) | Line::Comment(_) => {}
+                Line::Record(_) => {
+                    num_records_read += 1;
 
-            // If the tidy option is not set, the number of lines to read is limited to num_records.
-            if !options.tidy && count + 2 > options.num_records {
-                break;
+                    // If the tidy option is not set, the number of lines to read is limited to num_records.
+                    if !options.tidy && num_records_read + 1 > options.num_records {
+                        break;
+                    }
+                }
             }
         }
 
-        Ok(ModuleResult::with_result(
+        let module_result = ModuleResult::with_result(
             Some("GFF3".to_string()),
             Some("http://edamontology.org/format_1975".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: gff_version.is_some(),
+            magic: false,
+        });
+        Ok(match gff_version {
+            Some(version) => module_result.with_version(version.to_string()),
+            None => module_result,
+        })
     }
 }
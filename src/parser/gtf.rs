@@ -1,25 +1,24 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::BufRead;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 pub struct Gtf;
 
 impl Parser for Gtf {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = File::open(input_path)
-            .map(BufReader::new)
-            .map(noodles::gtf::Reader::new)?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::gtf::Reader::new(inner);
 
+        let mut num_records_read = 0;
         for (count, result) in reader.records().enumerate() {
             #[allow(unused_variables)]
-            let record = result?;
+            let record = result.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
@@ -30,6 +29,11 @@ impl Parser for Gtf {
         Ok(ModuleResult::with_result(
             Some("GTF".to_string()),
             Some("http://edamontology.org/format_2306".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: false,
+            magic: false,
+        }))
     }
 }
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+use std::rc::Rc;
+
+use noodles::bgzf::VirtualPosition;
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of bytes of the offending line kept as an excerpt.
+const EXCERPT_LENGTH: usize = 80;
+
+/// Where in the input a parser failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    /// 1-based index of the record being read. `None` if the failure occurred while reading the header.
+    pub record: Option<usize>,
+    /// 1-based line number. Only available for text formats.
+    pub line: Option<u64>,
+    /// Byte offset from the start of the input. For BGZF-compressed formats, the offset of the compressed block.
+    pub byte_offset: Option<u64>,
+    /// BGZF virtual offset. Only available for BAM and BCF.
+    pub virtual_offset: Option<u64>,
+    /// Beginning of the offending line. Only available for text formats.
+    pub excerpt: Option<String>,
+}
+
+impl Location {
+    /// Locates the record starting at the BGZF virtual position, for BAM and BCF.
+    pub fn at_virtual_position(record: usize, position: VirtualPosition) -> Self {
+        Self {
+            record: Some(record),
+            line: None,
+            byte_offset: Some(position.compressed()),
+            virtual_offset: Some(u64::from(position)),
+            excerpt: None,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.record {
+            Some(record) => write!(f, "record {}", record)?,
+            None => write!(f, "header")?,
+        }
+        if let Some(line) = self.line {
+            write!(f, ", line {}", line)?;
+        }
+        if let Some(byte_offset) = self.byte_offset {
+            write!(f, ", byte offset {}", byte_offset)?;
+        }
+        if let Some(virtual_offset) = self.virtual_offset {
+            write!(f, ", virtual offset {}", virtual_offset)?;
+        }
+        if let Some(excerpt) = &self.excerpt {
+            write!(f, ": {:?}", excerpt)?;
+        }
+        Ok(())
+    }
+}
+
+/// An error raised by a parser while reading a record, together with its `Location`.
+#[derive(Debug)]
+pub struct RecordError {
+    pub location: Location,
+    source: io::Error,
+}
+
+impl RecordError {
+    /// Creates an error at the location found beforehand, such as the first of the records found to be invalid after reading them.
+    pub fn new(location: Location, source: io::Error) -> Self {
+        Self { location, source }
+    }
+
+    /// Creates an error located by the BGZF virtual position at which the record starts.
+    pub fn at_virtual_position(
+        record: usize,
+        position: VirtualPosition,
+        source: io::Error,
+    ) -> Self {
+        Self::new(Location::at_virtual_position(record, position), source)
+    }
+
+    /// Creates an error located by the byte offset at which the block containing the record starts, for the binary formats not compressed by BGZF such as CRAM.
+    pub fn at_byte_offset(record: usize, byte_offset: u64, source: io::Error) -> Self {
+        Self {
+            location: Location {
+                record: Some(record),
+                line: None,
+                byte_offset: Some(byte_offset),
+                virtual_offset: None,
+                excerpt: None,
+            },
+            source,
+        }
+    }
+
+    /// The message of the underlying error, without the location.
+    pub fn message(&self) -> String {
+        self.source.to_string()
+    }
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at {})", self.source, self.location)
+    }
+}
+
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+#[derive(Debug, Default)]
+struct LineState {
+    /// 1-based number of the line.
+    number: u64,
+    /// Byte offset of the start of the line.
+    start: u64,
+    excerpt: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    offset: u64,
+    /// The line that the next consumed byte belongs to.
+    current: LineState,
+    /// The last line that has been consumed completely.
+    previous: Option<LineState>,
+    /// Bytes buffered but not consumed yet, used to complete the excerpt of a partially consumed line.
+    lookahead: Vec<u8>,
+    lookahead_offset: u64,
+}
+
+impl State {
+    fn advance(&mut self, buf: &[u8]) {
+        for chunk in buf.split_inclusive(|&b| b == b'\n') {
+            let room = EXCERPT_LENGTH.saturating_sub(self.current.excerpt.len());
+            let content = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+            self.current
+                .excerpt
+                .extend_from_slice(&content[..content.len().min(room)]);
+            self.offset += chunk.len() as u64;
+
+            if chunk.ends_with(b"\n") {
+                let next = LineState {
+                    number: self.current.number + 1,
+                    start: self.offset,
+                    excerpt: Vec::new(),
+                };
+                self.previous = Some(std::mem::replace(&mut self.current, next));
+            }
+        }
+    }
+
+    /// The line that has been read most recently, which is where the parser stopped.
+    fn last_line(&self) -> &LineState {
+        match &self.previous {
+            Some(previous) if self.offset == self.current.start => previous,
+            _ => &self.current,
+        }
+    }
+
+    fn excerpt(&self, line: &LineState) -> String {
+        let mut excerpt = line.excerpt.clone();
+        let is_partial = std::ptr::eq(line, &self.current);
+        if is_partial && self.lookahead_offset <= self.offset {
+            let skip = (self.offset - self.lookahead_offset) as usize;
+            let rest = self.lookahead.get(skip..).unwrap_or_default();
+            let rest = rest.split(|&b| b == b'\n').next().unwrap_or_default();
+            let room = EXCERPT_LENGTH.saturating_sub(excerpt.len());
+            excerpt.extend_from_slice(&rest[..rest.len().min(room)]);
+        }
+        String::from_utf8_lossy(&excerpt).trim_end().to_string()
+    }
+}
+
+/// A handle on the position of a `Tracked` reader, used to locate errors after handing the reader over to a parser.
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    state: Rc<RefCell<State>>,
+}
+
+impl Tracker {
+    /// Locates an error raised while reading the `record`-th record (1-based).
+    pub fn in_record(&self, record: usize, source: io::Error) -> RecordError {
+        self.locate(Some(record), source)
+    }
+
+    /// Number of bytes consumed by the parser so far.
+    pub fn byte_offset(&self) -> u64 {
+        self.state.borrow().offset
+    }
+
+    /// Locates an error raised while reading the header.
+    pub fn in_header(&self, source: io::Error) -> RecordError {
+        self.locate(None, source)
+    }
+
+    /// The location of the `record`-th record (1-based) which has just been read.
+    pub fn record_location(&self, record: usize) -> Location {
+        self.location(Some(record))
+    }
+
+    fn locate(&self, record: Option<usize>, source: io::Error) -> RecordError {
+        RecordError::new(self.location(record), source)
+    }
+
+    fn location(&self, record: Option<usize>) -> Location {
+        let state = self.state.borrow();
+        let line = state.last_line();
+        Location {
+            record,
+            line: Some(line.number),
+            byte_offset: Some(line.start),
+            virtual_offset: None,
+            excerpt: Some(state.excerpt(line)),
+        }
+    }
+}
+
+/// A `BufRead` wrapper which keeps track of the lines and bytes consumed by the parser reading from it.
+pub struct Tracked<R> {
+    inner: R,
+    state: Rc<RefCell<State>>,
+}
+
+/// Wraps `inner` so that the position of the parser reading from it can be reported on failure.
+pub fn track<R: BufRead>(inner: R) -> (Tracked<R>, Tracker) {
+    let state = Rc::new(RefCell::new(State {
+        current: LineState {
+            number: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    }));
+    let tracker = Tracker {
+        state: Rc::clone(&state),
+    };
+    (Tracked { inner, state }, tracker)
+}
+
+impl<R: BufRead> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Read through the buffer so that the lookahead is kept up to date.
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Tracked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.inner.fill_buf()?;
+        let mut state = self.state.borrow_mut();
+        state.lookahead.clear();
+        state
+            .lookahead
+            .extend_from_slice(&buf[..buf.len().min(EXCERPT_LENGTH)]);
+        state.lookahead_offset = state.offset;
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            // The bytes to be consumed are already buffered, so this does not read from the underlying reader.
+            if let Ok(buf) = self.inner.fill_buf() {
+                let amt = amt.min(buf.len());
+                self.state.borrow_mut().advance(&buf[..amt]);
+            }
+        }
+        self.inner.consume(amt);
+    }
+}
@@ -1,31 +1,64 @@
-use std::path::Path;
+pub mod validation;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use std::io::BufRead;
+
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
+use validation::Validator;
 
 pub struct Sam;
 
 impl Parser for Sam {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::sam::reader::Builder::default().build_from_path(input_path)?;
-        let header = reader.read_header()?;
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::sam::Reader::new(inner);
+        let header = reader.read_header().map_err(|e| tracker.in_header(e))?;
+        // the records are also checked against the header and against each other with `--strict`.
+        let mut validator = options.strict.then(|| Validator::new(&header));
+        let mut num_records_read = 0;
         for (count, result) in reader.records(&header).enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+            let record = result.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
+            if let Some(validator) = validator.as_mut() {
+                validator.check(&record, || tracker.record_location(count + 1));
+            }
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
                 break;
             }
         }
+        if let Some(validator) = validator {
+            validator.finish()?;
+        }
 
-        Ok(ModuleResult::with_result(
+        let module_result = ModuleResult::with_result(
             Some("SAM".to_string()),
             Some("http://edamontology.org/format_2573".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: !header.is_empty(),
+            magic: false,
+        });
+        Ok(with_header_details(module_result, &header))
+    }
+}
+
+// Attach the version of SAM in `@HD VN` and the sort order in `@HD SO`, which BAM also has.
+pub fn with_header_details(
+    mut module_result: ModuleResult,
+    header: &noodles::sam::Header,
+) -> ModuleResult {
+    if let Some(map) = header.header() {
+        module_result = module_result.with_version(map.version().to_string());
+        if let Some(sort_order) = map.sort_order() {
+            module_result = module_result.with_detail("sort_order", sort_order.to_string());
+        }
     }
+    module_result
 }
@@ -0,0 +1,333 @@
+use noodles::sam::alignment::Record;
+use noodles::sam::header::record::value::map::header::SortOrder;
+use noodles::sam::record::data::field::tag;
+use noodles::sam::Header;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::parser::location::{Location, RecordError};
+
+// The kinds of violations of the SAM specification that the records are checked for with `--strict`, named after the errors of Picard ValidateSamFile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Violation {
+    MismatchCigarSeqLength,
+    InvalidAlignmentStart,
+    CigarMapsOffReference,
+    RecordOutOfOrder,
+    InvalidFlagProperPair,
+    InvalidFlagMateUnmapped,
+    InvalidFlagFirstOfPair,
+    InvalidFlagSecondOfPair,
+    MismatchMateRefIndex,
+    MismatchMateAlignmentStart,
+    MismatchFlagMateUnmapped,
+    MismatchFlagMateNegStrand,
+    ReadGroupNotFound,
+}
+
+impl Violation {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::MismatchCigarSeqLength => "MISMATCH_CIGAR_SEQ_LENGTH",
+            Self::InvalidAlignmentStart => "INVALID_ALIGNMENT_START",
+            Self::CigarMapsOffReference => "CIGAR_MAPS_OFF_REFERENCE",
+            Self::RecordOutOfOrder => "RECORD_OUT_OF_ORDER",
+            Self::InvalidFlagProperPair => "INVALID_FLAG_PROPER_PAIR",
+            Self::InvalidFlagMateUnmapped => "INVALID_FLAG_MATE_UNMAPPED",
+            Self::InvalidFlagFirstOfPair => "INVALID_FLAG_FIRST_OF_PAIR",
+            Self::InvalidFlagSecondOfPair => "INVALID_FLAG_SECOND_OF_PAIR",
+            Self::MismatchMateRefIndex => "MISMATCH_MATE_REF_INDEX",
+            Self::MismatchMateAlignmentStart => "MISMATCH_MATE_ALIGNMENT_START",
+            Self::MismatchFlagMateUnmapped => "MISMATCH_FLAG_MATE_UNMAPPED",
+            Self::MismatchFlagMateNegStrand => "MISMATCH_FLAG_MATE_NEG_STRAND",
+            Self::ReadGroupNotFound => "READ_GROUP_NOT_FOUND",
+        }
+    }
+}
+
+// Where a segment is aligned, either as told by its own record or by the RNEXT, PNEXT and FLAG of its mate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    reference_sequence_id: Option<usize>,
+    alignment_start: Option<usize>,
+    is_unmapped: bool,
+    is_reverse_complemented: bool,
+}
+
+impl Placement {
+    fn of_segment(record: &Record) -> Self {
+        Self {
+            reference_sequence_id: record.reference_sequence_id(),
+            alignment_start: record.alignment_start().map(usize::from),
+            is_unmapped: record.flags().is_unmapped(),
+            is_reverse_complemented: record.flags().is_reverse_complemented(),
+        }
+    }
+
+    fn of_mate(record: &Record) -> Self {
+        Self {
+            reference_sequence_id: record.mate_reference_sequence_id(),
+            alignment_start: record.mate_alignment_start().map(usize::from),
+            is_unmapped: record.flags().is_mate_unmapped(),
+            is_reverse_complemented: record.flags().is_mate_reverse_complemented(),
+        }
+    }
+
+    // The violations of the mate telling `told` about the segment placed as `self`.
+    fn mismatches(&self, told: &Self) -> Vec<Violation> {
+        [
+            (
+                self.reference_sequence_id != told.reference_sequence_id,
+                Violation::MismatchMateRefIndex,
+            ),
+            (
+                self.alignment_start != told.alignment_start,
+                Violation::MismatchMateAlignmentStart,
+            ),
+            (
+                self.is_unmapped != told.is_unmapped,
+                Violation::MismatchFlagMateUnmapped,
+            ),
+            (
+                self.is_reverse_complemented != told.is_reverse_complemented,
+                Violation::MismatchFlagMateNegStrand,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(mismatched, violation)| mismatched.then_some(violation))
+        .collect()
+    }
+}
+
+// Checks the records of SAM and BAM against the header and against each other, counting the records violating the specification by the kind of violation.
+// The primary records of the pairs whose mate has not been read yet are kept by their names, so the memory grows with the number of them.
+pub struct Validator<'a> {
+    header: &'a Header,
+    sort_order: Option<SortOrder>,
+    num_records: usize,
+    num_invalid_records: usize,
+    counts: BTreeMap<Violation, usize>,
+    first_invalid_record: Option<Location>,
+    previous_coordinate: Option<(usize, usize)>,
+    previous_name: Option<Vec<u8>>,
+    // the placement of the segment and the one of its mate told by the record, keyed by the read name.
+    unpaired_mates: HashMap<Vec<u8>, (Placement, Placement)>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(header: &'a Header) -> Self {
+        Self {
+            header,
+            sort_order: header.header().and_then(|map| map.sort_order()),
+            num_records: 0,
+            num_invalid_records: 0,
+            counts: BTreeMap::new(),
+            first_invalid_record: None,
+            previous_coordinate: None,
+            previous_name: None,
+            unpaired_mates: HashMap::new(),
+        }
+    }
+
+    // Check the record which has just been read. `location` is only called for the first invalid record, which is reported as where the violations start.
+    pub fn check(&mut self, record: &Record, location: impl FnOnce() -> Location) {
+        let mut violations = Vec::new();
+        self.check_lengths(record, &mut violations);
+        self.check_position(record, &mut violations);
+        self.check_order(record, &mut violations);
+        self.check_mate(record, &mut violations);
+        self.check_read_group(record, &mut violations);
+
+        self.num_records += 1;
+        if violations.is_empty() {
+            return;
+        }
+        violations.sort();
+        violations.dedup();
+        for violation in violations {
+            *self.counts.entry(violation).or_default() += 1;
+        }
+        self.num_invalid_records += 1;
+        if self.first_invalid_record.is_none() {
+            self.first_invalid_record = Some(location());
+        }
+    }
+
+    // Fail with the number of the records violating the specification by the kind of violation, located at the first invalid record.
+    pub fn finish(self) -> Result<(), RecordError> {
+        let Some(location) = self.first_invalid_record else {
+            return Ok(());
+        };
+        let counts = self
+            .counts
+            .iter()
+            .map(|(violation, count)| format!("{} ({})", violation.name(), count))
+            .collect::<Vec<String>>()
+            .join(", ");
+        Err(RecordError::new(
+            location,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} of {} records violate the SAM specification: {}",
+                    self.num_invalid_records, self.num_records, counts
+                ),
+            ),
+        ))
+    }
+
+    // The length of QUAL is not checked here, as the readers already reject the records whose QUAL differs from SEQ in length.
+    fn check_lengths(&self, record: &Record, violations: &mut Vec<Violation>) {
+        // `*` in SEQ and CIGAR leaves the length unknown.
+        let sequence_length = record.sequence().len();
+        let cigar = record.cigar();
+        if sequence_length > 0 && !cigar.is_empty() && cigar.read_length() != sequence_length {
+            violations.push(Violation::MismatchCigarSeqLength);
+        }
+    }
+
+    // The alignment must start and end within the reference sequence given by `@SQ LN`.
+    fn check_position(&self, record: &Record, violations: &mut Vec<Violation>) {
+        if record.flags().is_unmapped() {
+            return;
+        }
+        let Some((_, reference_sequence)) = record
+            .reference_sequence_id()
+            .and_then(|id| self.header.reference_sequences().get_index(id))
+        else {
+            return;
+        };
+        let length = reference_sequence.length().get();
+        match record.alignment_start().map(usize::from) {
+            Some(start) if start <= length => {
+                let end = start + record.cigar().alignment_span();
+                if end > length + 1 {
+                    violations.push(Violation::CigarMapsOffReference);
+                }
+            }
+            _ => violations.push(Violation::InvalidAlignmentStart),
+        }
+    }
+
+    // The records must be in the order declared by `@HD SO`, with the unplaced records at the end of the ones sorted by coordinate.
+    // The records sorted by name are accepted in either the lexicographic order or the natural order of samtools, comparing the numbers in the names by their values.
+    fn check_order(&mut self, record: &Record, violations: &mut Vec<Violation>) {
+        match self.sort_order {
+            Some(SortOrder::Coordinate) => {
+                let coordinate = (
+                    record.reference_sequence_id().unwrap_or(usize::MAX),
+                    record.alignment_start().map_or(0, usize::from),
+                );
+                if self
+                    .previous_coordinate
+                    .is_some_and(|previous| coordinate < previous)
+                {
+                    violations.push(Violation::RecordOutOfOrder);
+                }
+                self.previous_coordinate = Some(coordinate);
+            }
+            Some(SortOrder::QueryName) => {
+                let name = record
+                    .read_name()
+                    .map(|name| AsRef::<[u8]>::as_ref(name).to_vec())
+                    .unwrap_or_default();
+                if let Some(previous) = &self.previous_name {
+                    if name < *previous && compare_natural(&name, previous) == Ordering::Less {
+                        violations.push(Violation::RecordOutOfOrder);
+                    }
+                }
+                self.previous_name = Some(name);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_mate(&mut self, record: &Record, violations: &mut Vec<Violation>) {
+        let flags = record.flags();
+        // the flags about the mate are meaningless for a segment without one.
+        if !flags.is_segmented() {
+            for (is_set, violation) in [
+                (
+                    flags.is_properly_aligned(),
+                    Violation::InvalidFlagProperPair,
+                ),
+                (flags.is_mate_unmapped(), Violation::InvalidFlagMateUnmapped),
+                (flags.is_first_segment(), Violation::InvalidFlagFirstOfPair),
+                (flags.is_last_segment(), Violation::InvalidFlagSecondOfPair),
+            ] {
+                if is_set {
+                    violations.push(violation);
+                }
+            }
+            return;
+        }
+
+        // the fields about the mate are compared with the primary record of the mate.
+        if flags.is_secondary() || flags.is_supplementary() {
+            return;
+        }
+        let Some(name) = record.read_name() else {
+            return;
+        };
+        let name = AsRef::<[u8]>::as_ref(name).to_vec();
+        let segment = Placement::of_segment(record);
+        let mate = Placement::of_mate(record);
+        match self.unpaired_mates.remove(&name) {
+            Some((mate_segment, told_segment)) => {
+                violations.extend(mate_segment.mismatches(&mate));
+                violations.extend(segment.mismatches(&told_segment));
+            }
+            None => {
+                self.unpaired_mates.insert(name, (segment, mate));
+            }
+        }
+    }
+
+    fn check_read_group(&self, record: &Record, violations: &mut Vec<Violation>) {
+        let Some(read_group) = record
+            .data()
+            .get(&tag::READ_GROUP)
+            .and_then(|value| value.as_str())
+        else {
+            return;
+        };
+        if !self.header.read_groups().contains_key(read_group) {
+            violations.push(Violation::ReadGroupNotFound);
+        }
+    }
+}
+
+// Compare the names in the natural order, in which the runs of digits are compared by their values, such as "r9" before "r10".
+fn compare_natural(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let digits_a = a[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let digits_b = b[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let number_a = trim_leading_zeros(&a[i..i + digits_a]);
+            let number_b = trim_leading_zeros(&b[j..j + digits_b]);
+            let ordering = number_a
+                .len()
+                .cmp(&number_b.len())
+                .then_with(|| number_a.cmp(number_b));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            i += digits_a;
+            j += digits_b;
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+fn trim_leading_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&c| c == b'0').count();
+    &digits[zeros..]
+}
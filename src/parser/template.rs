@@ -1,24 +1,23 @@
 use std::io::BufRead;
-use std::path::Path;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
 
 #[allow(unused)]
 pub struct Template;
 
 impl Parser for Template {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
         /*
         This is a dummy implementation. Replace this with the actual algorithm to determine the file format.
         */
 
-        let file = std::fs::File::open(input_path)?;
-        let reader = std::io::BufReader::new(file);
+        // Keep track of the position of the reader, so that a failure can be located in the input.
+        let (reader, tracker) = location::track(reader);
 
         // Read the header if the format has one.
         /*
@@ -26,8 +25,10 @@ impl Parser for Template {
          */
 
         // Read the input line by line and check if it matches the expected format.
+        let mut num_records_read = 0;
         for (count, line) in reader.lines().enumerate() {
-            let _line = line?;
+            let _line = line.map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
 
             // Do something with the line here
 
@@ -45,19 +46,28 @@ impl Parser for Template {
         Ok(ModuleResult::with_result(
             Some("EDAM label".to_string()),
             Some("http://edamontology.org/format_ EDAM id".to_string()),
-        ))
+        )
+        // Report what has been observed while reading the input. It is used to compute the confidence score.
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: false,
+            magic: false,
+        }))
     }
 }
 
 /*
+#[cfg(test)]
+use std::path::Path;
+
+#[cfg(test)]
+use crate::parser::tests::options;
+
 #[test]
 fn test_template() {
     let input_path = Path::new("./tests/inputs/template");
-    let option = InvokeOptions {
-        tidy: true,
-        no_decompress: false,
-        num_records: 100000,
-    };
+    // the options shared by the tests in the `tests` module of src/parser.rs.
+    let option = options();
 
     let parser = Template;
     let module_result = parser.determine_from_path(input_path, &option).unwrap();
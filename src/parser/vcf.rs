@@ -1,33 +1,84 @@
-use std::path::Path;
+pub mod validation;
 
-use crate::module::{InvokeOptions, ModuleResult};
-use crate::parser::Parser;
+use std::io::{self, BufRead};
+
+use crate::module::{Evidence, InvokeOptions, ModuleResult};
+use crate::parser::{location, Parser};
+use crate::truncation;
+use validation::Validator;
 
 pub struct Vcf;
 
 impl Parser for Vcf {
-    fn determine_from_path(
+    fn determine_from_reader(
         &self,
-        input_path: &Path,
+        reader: &mut dyn BufRead,
         options: &InvokeOptions,
     ) -> anyhow::Result<ModuleResult> {
-        let mut reader = noodles::vcf::reader::Builder::default().build_from_path(input_path)?;
+        // bgzipped VCF is read through BGZF, when it is given as is, such as with `--no-decompress`.
+        if truncation::is_bgzf(reader.fill_buf()?) {
+            return self.determine_from_reader(&mut noodles::bgzf::Reader::new(reader), options);
+        }
+
+        let (inner, tracker) = location::track(reader);
+        let mut reader = noodles::vcf::Reader::new(inner);
 
-        let header = reader.read_header()?;
+        let header = reader.read_header().map_err(|e| tracker.in_header(e))?;
+        // the records are also checked against the header with `--strict`.
+        let mut validator = options.strict.then(|| Validator::new(&header));
 
-        for (count, result) in reader.records(&header).enumerate() {
-            #[allow(unused_variables)]
-            let record = result?;
+        // The lines are read here instead of by the reader, so that the sample columns are counted before the reader fills or drops them to fit the header.
+        let mut line = String::new();
+        let mut num_records_read = 0;
+        for count in 0.. {
+            line.clear();
+            if read_line(reader.get_mut(), &mut line)
+                .map_err(|e| tracker.in_record(count + 1, e))?
+                == 0
+            {
+                break;
+            }
+            let record = noodles::vcf::Record::try_from((&header, line.as_str()))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                .map_err(|e| tracker.in_record(count + 1, e))?;
+            num_records_read = count + 1;
+            if let Some(validator) = validator.as_mut() {
+                // the columns after INFO are FORMAT and the samples.
+                let num_samples = line.split('\t').count().saturating_sub(9);
+                validator.check(&record, num_samples, || tracker.record_location(count + 1));
+            }
 
             // If the tidy option is not set, the number of lines to read is limited to num_records.
             if !options.tidy && count + 2 > options.num_records {
                 break;
             }
         }
+        if let Some(validator) = validator {
+            validator.finish()?;
+        }
 
+        let file_format = header.file_format();
         Ok(ModuleResult::with_result(
             Some("VCF".to_string()),
             Some("http://edamontology.org/format_3016".to_string()),
-        ))
+        )
+        .with_evidence(Evidence {
+            records: num_records_read,
+            header: true,
+            magic: false,
+        })
+        .with_version(format!("{}.{}", file_format.major(), file_format.minor())))
+    }
+}
+
+// Read a line without the line break, as the reader of noodles does.
+fn read_line<R: BufRead>(reader: &mut R, buf: &mut String) -> io::Result<usize> {
+    let n = reader.read_line(buf)?;
+    if buf.ends_with('\n') {
+        buf.pop();
+        if buf.ends_with('\r') {
+            buf.pop();
+        }
     }
+    Ok(n)
 }
@@ -0,0 +1,217 @@
+use noodles::vcf::header::Number;
+use noodles::vcf::record::genotypes::sample;
+use noodles::vcf::record::info::field;
+use noodles::vcf::{Header, Record};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use crate::parser::location::{Location, RecordError};
+
+// The kinds of inconsistencies between the header and the records of VCF and BCF that the records are checked for with `--strict`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Violation {
+    InfoNotDeclared,
+    InfoNumberMismatch,
+    FormatNotDeclared,
+    FormatNumberMismatch,
+    ContigNotFound,
+    RecordOutOfOrder,
+    SampleCountMismatch,
+}
+
+impl Violation {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::InfoNotDeclared => "INFO_NOT_DECLARED",
+            Self::InfoNumberMismatch => "INFO_NUMBER_MISMATCH",
+            Self::FormatNotDeclared => "FORMAT_NOT_DECLARED",
+            Self::FormatNumberMismatch => "FORMAT_NUMBER_MISMATCH",
+            Self::ContigNotFound => "CONTIG_NOT_FOUND",
+            Self::RecordOutOfOrder => "RECORD_OUT_OF_ORDER",
+            Self::SampleCountMismatch => "SAMPLE_COUNT_MISMATCH",
+        }
+    }
+}
+
+// Checks the records of VCF and BCF against the header, counting the records violating it by the kind of violation.
+// The types of the values are not checked here, as the readers parse the values of the declared keys by their types and reject the ones which do not fit.
+pub struct Validator<'a> {
+    header: &'a Header,
+    num_records: usize,
+    num_invalid_records: usize,
+    counts: BTreeMap<Violation, usize>,
+    first_invalid_record: Option<Location>,
+    // the position of the last record on each contig.
+    last_positions: HashMap<String, usize>,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(header: &'a Header) -> Self {
+        Self {
+            header,
+            num_records: 0,
+            num_invalid_records: 0,
+            counts: BTreeMap::new(),
+            first_invalid_record: None,
+            last_positions: HashMap::new(),
+        }
+    }
+
+    // Check the record which has just been read, with the number of the sample columns in it. `location` is only called for the first invalid record.
+    pub fn check(
+        &mut self,
+        record: &Record,
+        num_samples: usize,
+        location: impl FnOnce() -> Location,
+    ) {
+        let mut violations = Vec::new();
+        self.check_info(record, &mut violations);
+        self.check_genotypes(record, &mut violations);
+        self.check_contig(record, &mut violations);
+        if num_samples != self.header.sample_names().len() {
+            violations.push(Violation::SampleCountMismatch);
+        }
+
+        self.num_records += 1;
+        if violations.is_empty() {
+            return;
+        }
+        violations.sort();
+        violations.dedup();
+        for violation in violations {
+            *self.counts.entry(violation).or_default() += 1;
+        }
+        self.num_invalid_records += 1;
+        if self.first_invalid_record.is_none() {
+            self.first_invalid_record = Some(location());
+        }
+    }
+
+    // Fail with the number of the records violating the header by the kind of violation, located at the first invalid record.
+    pub fn finish(self) -> Result<(), RecordError> {
+        let Some(location) = self.first_invalid_record else {
+            return Ok(());
+        };
+        let counts = self
+            .counts
+            .iter()
+            .map(|(violation, count)| format!("{} ({})", violation.name(), count))
+            .collect::<Vec<String>>()
+            .join(", ");
+        Err(RecordError::new(
+            location,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} of {} records violate the VCF specification: {}",
+                    self.num_invalid_records, self.num_records, counts
+                ),
+            ),
+        ))
+    }
+
+    // The keys in INFO must be declared in `##INFO`, and their values must be as many as its `Number`.
+    fn check_info(&self, record: &Record, violations: &mut Vec<Violation>) {
+        let num_alternate_alleles = record.alternate_bases().len();
+        for (key, value) in record.info().keys().zip(record.info().values()) {
+            let Some(info) = self.header.infos().get(key) else {
+                violations.push(Violation::InfoNotDeclared);
+                continue;
+            };
+            // the ploidy is unknown for INFO, so the number of the genotypes is not checked.
+            let expected = expected_count(info.number(), num_alternate_alleles, None);
+            let actual = value.map(|value| match value {
+                field::Value::Flag => 0,
+                field::Value::Array(array) => match array {
+                    field::value::Array::Integer(values) => values.len(),
+                    field::value::Array::Float(values) => values.len(),
+                    field::value::Array::Character(values) => values.len(),
+                    field::value::Array::String(values) => values.len(),
+                },
+                _ => 1,
+            });
+            if expected.zip(actual).is_some_and(|(e, a)| e != a) {
+                violations.push(Violation::InfoNumberMismatch);
+            }
+        }
+    }
+
+    // The keys in FORMAT must be declared in `##FORMAT`, and the values of each sample must be as many as its `Number`.
+    fn check_genotypes(&self, record: &Record, violations: &mut Vec<Violation>) {
+        let genotypes = record.genotypes();
+        let formats = genotypes
+            .keys()
+            .iter()
+            .map(|key| self.header.formats().get(key))
+            .collect::<Vec<_>>();
+        if formats.iter().any(Option::is_none) {
+            violations.push(Violation::FormatNotDeclared);
+        }
+
+        let num_alternate_alleles = record.alternate_bases().len();
+        for sample in genotypes.values() {
+            let ploidy = sample
+                .genotype()
+                .and_then(Result::ok)
+                .map(|genotype| genotype.len());
+            for (format, value) in formats.iter().zip(sample.values()) {
+                let (Some(format), Some(value)) = (format, value) else {
+                    continue;
+                };
+                let expected = expected_count(format.number(), num_alternate_alleles, ploidy);
+                let actual = match value {
+                    sample::Value::Array(array) => match array {
+                        sample::value::Array::Integer(values) => values.len(),
+                        sample::value::Array::Float(values) => values.len(),
+                        sample::value::Array::Character(values) => values.len(),
+                        sample::value::Array::String(values) => values.len(),
+                    },
+                    _ => 1,
+                };
+                if expected.is_some_and(|expected| expected != actual) {
+                    violations.push(Violation::FormatNumberMismatch);
+                }
+            }
+        }
+    }
+
+    // CHROM must be declared in `##contig`, and the records on each contig must be sorted by POS.
+    fn check_contig(&mut self, record: &Record, violations: &mut Vec<Violation>) {
+        let chromosome = record.chromosome().to_string();
+        if !self.header.contigs().contains_key(chromosome.as_str()) {
+            violations.push(Violation::ContigNotFound);
+        }
+        let position = usize::from(record.position());
+        match self.last_positions.get_mut(&chromosome) {
+            Some(last_position) => {
+                if position < *last_position {
+                    violations.push(Violation::RecordOutOfOrder);
+                }
+                *last_position = position;
+            }
+            None => {
+                self.last_positions.insert(chromosome, position);
+            }
+        }
+    }
+}
+
+// The number of the values that a field with `number` must have, or `None` if it is not fixed.
+fn expected_count(
+    number: Number,
+    num_alternate_alleles: usize,
+    ploidy: Option<usize>,
+) -> Option<usize> {
+    match number {
+        Number::Count(n) => Some(n),
+        Number::A => Some(num_alternate_alleles),
+        Number::R => Some(num_alternate_alleles + 1),
+        // the number of the unordered genotypes of the ploidy over the reference and alternate alleles.
+        Number::G => ploidy.map(|ploidy| {
+            (1..=ploidy).fold(1, |combinations, k| {
+                combinations * (num_alternate_alleles + k) / k
+            })
+        }),
+        Number::Unknown => None,
+    }
+}
@@ -2,24 +2,33 @@ use anyhow::{bail, Result};
 use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
 use log::{debug, warn};
+use lz4_flex::frame::FrameDecoder;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::io::{BufRead, BufReader, Cursor, Read, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use tempfile::{NamedTempFile, TempDir};
+use xz2::read::XzDecoder;
+use xz2::stream::Stream;
 
-use crate::buffered_read_seek::OnetimeRewindableReader;
+use crate::limits::{DeadlineReader, Guard};
 use crate::module::InvokeOptions;
+use crate::truncation::{block_size, MAX_BLOCK_SIZE};
 
 static STDIN_IS_USED: AtomicBool = AtomicBool::new(false);
 
+/// Number of bytes read from the beginning of the input to infer the compression format. The whole first block is read instead if the input is in BGZF.
+pub const HEADER_SIZE: usize = 1024;
+
+/// Sampled inputs up to this size are kept in memory and parsed without being written to a temporary file.
+const MAX_IN_MEMORY_BYTES: usize = 64 * 1024 * 1024;
+
 #[derive(Debug)]
 pub enum Source {
     FilePath(PathBuf),
     TempFile(NamedTempFile),
     Stdin,
-    #[allow(dead_code)]
     Memory(Vec<u8>),
 }
 
@@ -46,7 +55,7 @@ impl std::fmt::Display for Source {
             Self::FilePath(p) => p.display().fmt(f),
             Self::TempFile(t) => t.path().display().fmt(f),
             Self::Stdin => write!(f, "STDIN"),
-            Self::Memory(_) => write!(f, "MEMORY"), // non expected case
+            Self::Memory(_) => write!(f, "MEMORY"),
         }
     }
 }
@@ -63,215 +72,111 @@ impl Source {
         }
     }
 
-    /*
-    pub fn as_memory(&self) -> Option<&Vec<u8>> {
+    // write the input kept in memory into a tempfile, for the modules that need a path to the input such as CWL modules.
+    pub fn into_file_backed(self, temp_dir: &TempDir) -> Result<Self> {
         match self {
-            Self::Memory(m) => Some(m),
-            _ => None,
+            Self::Memory(bytes) => {
+                let mut tempfile = NamedTempFile::new_in(temp_dir)?;
+                tempfile.write_all(&bytes)?;
+                Ok(Self::TempFile(tempfile))
+            }
+            source => Ok(source),
         }
     }
-    */
 
-    // if the input is compressed and no_decompress is false, decompress the input, save it into tempfile and return Source::FilePath(tempfile path)
+    // if the input is compressed and no_decompress is false, decompress the input, and return it as Source::Memory, or Source::TempFile if it is too large to be kept in memory
     // if the input is either not compressed or no_decompress is true, return None
-    pub fn decompress_into_tempfile_from_filepath_if_needed(
+    // the compression layers of the input are returned outermost first.
+    pub fn decompress_from_filepath_if_needed(
         input_path: &Path,
         options: &InvokeOptions,
         temp_dir: &TempDir,
         cwl_modules_exists: bool,
-    ) -> Result<(Option<Self>, CompressedFormat)> {
-        let mut file = File::open(input_path)?;
-
-        // read first 100 bytes from reader in order to infer compression format
-        let mut buffer = [0; 100];
-        let bytes_read = file.read(&mut buffer)?;
-
-        // rewind the reader to the beginning
-        file.rewind()?;
-
-        // TODO check
-        // if not inferrable, then None.
-        let compressed_format: CompressedFormat = infer::get(&buffer[..bytes_read]).map_or_else(|| {
-            // type was not inferred, return None
-            debug!("Compressed format of the input is not inferrable, or the input is not compressed. Parsing the input as is.");
-            CompressedFormat::None
-        },
-        |inferred_type| {
-            match inferred_type.extension() {
-            "gz" => {
-                // check if the gz file is in BGZF format
-                if is_gzfile_in_bgzf(&buffer) {
-                    debug!("Provided input is in BGZF format");
-                    CompressedFormat::Bgzf
-                } else {
-                    debug!("Provided input is in GZ format");
-                    CompressedFormat::GZ
-                }
-            }
-            "bz2" => {
-                debug!("Provided input is in BZ2 format");
-                CompressedFormat::BZ2
-            }
-            _ => {
-                warn!("Provided input is in compressed format not supported by this tool. Parsing the input as is.");
-                CompressedFormat::None
-            }
-        }}
-    );
-
-        // if input is plain text or in BGZF, we are not going to decompress it
-        let mut inferred_reader: Box<dyn Read> = match compressed_format {
-            CompressedFormat::Bgzf => {
-                // if BGZF, save the reader as is
-                return Ok((None, CompressedFormat::Bgzf));
-            }
-            CompressedFormat::GZ => {
-                // if GZ, save the GzDecoder reader
-                let decoder = GzDecoder::new(file);
-                Box::new(decoder)
-            }
-            CompressedFormat::BZ2 => {
-                // if BZ2, save the BzDecoder reader
-                let decoder = BzDecoder::new(file);
-                Box::new(decoder)
-            }
-            CompressedFormat::None => {
-                // if None, save the reader as is
-                return Ok((None, CompressedFormat::None));
-            }
-        };
+    ) -> Result<(Option<Self>, Vec<CompressedFormat>)> {
+        let file = File::open(input_path)?;
 
-        // here the input is compressed and not BGZF
+        // if no_decompress is true, only report the outermost compression format
         if options.no_decompress {
-            Ok((None, compressed_format))
-        } else {
-            if cwl_modules_exists && !options.tidy {
-                bail!("The `--tidy` options is required when using CWL modules with compressed input files. If you want to treat the input file as is and not decompress it, please use the `--no-decompress` option.");
-            }
-            let decompressed_tempfile = Self::read_numrecords_save_into_tempfile(
-                &mut inferred_reader,
-                options,
-                temp_dir,
-                false,
-            )?;
-
-            Ok((Some(decompressed_tempfile), compressed_format))
+            let (header, _) = peek(file)?;
+            return Ok((None, outermost_layer(&header).into_iter().collect()));
+        }
+
+        let mut unwrapped = unwrap_layers(Box::new(file), options)?;
+
+        // if input is plain text or BAM/BCF in BGZF, we are not going to decompress it
+        if unwrapped.layers.is_empty() {
+            return Ok((None, unwrapped.layers));
         }
+
+        if cwl_modules_exists && !options.tidy {
+            bail!("The `--tidy` options is required when using CWL modules with compressed input files. If you want to treat the input file as is and not decompress it, please use the `--no-decompress` option.");
+        }
+        let decompressed_source = Self::read_numrecords_into_source(
+            &mut unwrapped.reader,
+            options,
+            temp_dir,
+            is_binary(&unwrapped.header),
+        )?;
+
+        Ok((Some(decompressed_source), unwrapped.layers))
     }
 
-    // if the input from stdin is compressed and no_decompress is false, decompress the input, save it into tempfile and return Source::FilePath(tempfile path)
-    // if the input from stdin is either not compressed or no_decompress is true, save it into tempfile and return Source::FilePath(tempfile path)
-    #[allow(clippy::significant_drop_tightening)]
-    pub fn convert_into_tempfile_from_stdin(
+    // if the input from stdin is compressed and no_decompress is false, decompress the input, and return it as Source::Memory, or Source::TempFile if it is too large to be kept in memory
+    // if the input from stdin is either not compressed or no_decompress is true, return it as is in the same way
+    pub fn read_from_stdin(
         options: &InvokeOptions,
         temp_dir: &TempDir,
-    ) -> Result<(Self, CompressedFormat)> {
-        let stdin = std::io::stdin();
-        let handle = stdin.lock();
-
-        let mut onetime_reader = OnetimeRewindableReader::new(handle);
-
-        // read first 100 bytes from reader in order to infer compression format
-        let mut buffer = [0; 100];
-        let bytes_read = onetime_reader.read(&mut buffer)?;
-
-        // rewind the reader to the beginning
-        onetime_reader.rewind()?;
-
-        let mut is_bgzf = false;
-
-        // if not inferrable, then None.
-        // TODO refactor into a function
-        let compressed_format: CompressedFormat = infer::get(&buffer[..bytes_read]).map_or_else(||
-            {
-            // type was not inferred, return None
-            debug!("Compressed format of the input is not inferrable, or the input is not compressed. Parsing the input as is.");
-            CompressedFormat::None
-        }, |inferred_type| {
-            match inferred_type.extension() {
-                "gz" => {
-                    // check if the gz file is in BGZF format
-                    if is_gzfile_in_bgzf(&buffer) {
-                        is_bgzf = true;
-                        debug!("Provided input is in BGZF format");
-                        CompressedFormat::Bgzf
-                    } else {
-                        debug!("Provided input is in GZ format");
-                        CompressedFormat::GZ
-                    }
-                }
-                "bz2" => {
-                    debug!("Provided input is in BZ2 format");
-                    CompressedFormat::BZ2
-                }
-                _ => {
-                    warn!("Provided input is in compressed format not supported by this tool. Parsing the input as is.");
-                    CompressedFormat::None
-                }
-            }
-        });
+    ) -> Result<(Self, Vec<CompressedFormat>)> {
+        Self::read_from_reader(std::io::stdin().lock(), options, temp_dir)
+    }
 
+    // read the input which cannot be seeked, such as STDIN or a member of an archive, in the same way as `read_from_stdin`.
+    pub fn read_from_reader<'a, R: Read + 'a>(
+        reader: R,
+        options: &InvokeOptions,
+        temp_dir: &TempDir,
+    ) -> Result<(Self, Vec<CompressedFormat>)> {
         // use the reader as is if no_decompress is true
-        let mut inferred_reader: Box<dyn Read> = if options.no_decompress {
-            Box::new(onetime_reader)
+        let (mut reader, layers, is_binary) = if options.no_decompress {
+            let (header, reader) = peek(reader)?;
+            let layers = outermost_layer(&header).into_iter().collect();
+            (reader, layers, true)
         } else {
-            match compressed_format {
-                CompressedFormat::Bgzf => {
-                    // if BGZF, save the reader as is
-                    Box::new(onetime_reader)
-                }
-                CompressedFormat::GZ => {
-                    // if GZ, save the GzDecoder reader
-                    let decoder = GzDecoder::new(onetime_reader);
-                    Box::new(decoder)
-                }
-                CompressedFormat::BZ2 => {
-                    // if BZ2, save the BzDecoder reader
-                    let decoder = BzDecoder::new(onetime_reader);
-                    Box::new(decoder)
-                }
-                CompressedFormat::None => {
-                    // if None, save the reader as is
-                    Box::new(onetime_reader)
-                }
-            }
+            let unwrapped = unwrap_layers(Box::new(reader), options)?;
+            let is_binary = is_binary(&unwrapped.header);
+            (unwrapped.reader, unwrapped.layers, is_binary)
         };
 
-        let tempfile_from_stdin = Self::read_numrecords_save_into_tempfile(
-            &mut inferred_reader,
-            options,
-            temp_dir,
-            is_bgzf || options.no_decompress,
-        )?;
+        let source = Self::read_numrecords_into_source(&mut reader, options, temp_dir, is_binary)?;
 
-        Ok((tempfile_from_stdin, compressed_format))
+        Ok((source, layers))
     }
 
-    fn read_numrecords_save_into_tempfile<R: Read>(
+    fn read_numrecords_into_source<R: Read>(
         reader: &mut R,
         options: &InvokeOptions,
         temp_dir: &TempDir,
         is_binary: bool,
     ) -> Result<Self> {
-        // create a writer for tempfile
-        let mut tempfile = NamedTempFile::new_in(temp_dir)?;
+        // keep the input in memory, and move it into a tempfile only if it gets too large
+        let mut sample = SampleBuffer::new(temp_dir);
 
-        // if the input is binary, such as BGZF, read (100 * num_records) bytes and save it into tempfile
+        // if the input is binary, such as BGZF, read (100 * num_records) bytes and keep it as the sample
         if is_binary {
             let total_bytes_copied: u64 = if options.tidy {
-                std::io::copy(reader, &mut tempfile)?
+                std::io::copy(reader, &mut sample)?
             } else {
                 let bytes_to_copy = 100 * options.num_records;
                 let mut limited_src = reader.take(bytes_to_copy as u64);
-                std::io::copy(&mut limited_src, &mut tempfile)?
+                std::io::copy(&mut limited_src, &mut sample)?
             };
-            debug!("Bytes read from STDIN: {}", total_bytes_copied);
+            debug!("Bytes read from the input: {}", total_bytes_copied);
         }
-        // if not in binary, read the first 4 * num_records lines (plus header) and save it into tempfile
+        // if not in binary, read the first 4 * num_records lines (plus header) and keep it as the sample
         else {
             let mut bufreader = BufReader::new(reader);
 
-            let mut line_buffer = String::new();
+            let mut line_buffer = Vec::new();
             let mut count = 0;
             let mut header_count = 0;
             let numlines_to_read = 4 * options.num_records;
@@ -279,13 +184,13 @@ impl Source {
             while options.tidy || count < numlines_to_read {
                 line_buffer.clear();
 
-                let bytes_read = bufreader.read_line(&mut line_buffer)?;
+                let bytes_read = bufreader.read_until(b'\n', &mut line_buffer)?;
                 if bytes_read == 0 {
                     break;
                 }
 
                 // if the line read is presumably a comment line, do not count it as a read line until the count reaches the max_header_lines
-                if (line_buffer.starts_with('#') || line_buffer.starts_with('@'))
+                if (line_buffer.starts_with(b"#") || line_buffer.starts_with(b"@"))
                     && header_count < max_header_lines
                 {
                     header_count += 1;
@@ -293,21 +198,208 @@ impl Source {
                     count += 1
                 }
 
-                // write line into tempfile
-                tempfile.write_all(line_buffer.as_bytes())?;
+                // write line into the sample
+                sample.write_all(&line_buffer)?;
+            }
+        }
+
+        Ok(sample.into_source())
+    }
+}
+
+// A writer which keeps the sampled input in memory, and moves it into a tempfile once it grows larger than `MAX_IN_MEMORY_BYTES`.
+enum SampleBuffer<'a> {
+    Memory(Vec<u8>, &'a TempDir),
+    File(NamedTempFile),
+}
+
+impl<'a> SampleBuffer<'a> {
+    const fn new(temp_dir: &'a TempDir) -> Self {
+        Self::Memory(Vec::new(), temp_dir)
+    }
+
+    fn into_source(self) -> Source {
+        match self {
+            Self::Memory(bytes, _) => Source::Memory(bytes),
+            Self::File(tempfile) => Source::TempFile(tempfile),
+        }
+    }
+}
+
+impl Write for SampleBuffer<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Memory(bytes, temp_dir) => {
+                if bytes.len() + buf.len() > MAX_IN_MEMORY_BYTES {
+                    debug!("The sampled input is too large to be kept in memory. Writing it into a tempfile.");
+                    let mut tempfile = NamedTempFile::new_in(*temp_dir)?;
+                    tempfile.write_all(bytes)?;
+                    tempfile.write_all(buf)?;
+                    *self = Self::File(tempfile);
+                } else {
+                    bytes.extend_from_slice(buf);
+                }
+                Ok(buf.len())
             }
+            Self::File(tempfile) => tempfile.write(buf),
         }
+    }
 
-        Ok(Self::TempFile(tempfile))
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Memory(..) => Ok(()),
+            Self::File(tempfile) => tempfile.flush(),
+        }
     }
 }
 
+// read the first bytes of the input, and return them with the reader which reads the input from the beginning.
+pub fn peek<'a, R: Read + 'a>(reader: R) -> Result<(Vec<u8>, Box<dyn Read + 'a>)> {
+    let mut reader = BufReader::new(reader);
+    let header = read_header(&mut reader)?;
+    Ok((header.clone(), Box::new(Cursor::new(header).chain(reader))))
+}
+
+// read the first `HEADER_SIZE` bytes of the input, or the whole first block if the input is in BGZF, whose content cannot be inflated from a part of the block.
+pub fn read_header<R: Read>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    reader.take(HEADER_SIZE as u64).read_to_end(&mut header)?;
+    if is_gzfile_in_bgzf(&header) {
+        if let Some(size) = block_size(&header).filter(|&size| size > header.len()) {
+            let remaining = size.min(MAX_BLOCK_SIZE) - header.len();
+            reader.take(remaining as u64).read_to_end(&mut header)?;
+        }
+    }
+    Ok(header)
+}
+
+// the outermost compression format to be reported, if the input is compressed and not BAM/BCF in BGZF.
+fn outermost_layer(header: &[u8]) -> Option<CompressedFormat> {
+    match infer_compressed_format(header) {
+        CompressedFormat::None | CompressedFormat::Bgzf => None,
+        compressed_format => Some(compressed_format),
+    }
+}
+
+// The content of the input after the compression layers have been decompressed.
+struct Unwrapped<'a> {
+    reader: Box<dyn Read + 'a>,
+    // the compression layers, outermost first
+    layers: Vec<CompressedFormat>,
+    // the first bytes of the content
+    header: Vec<u8>,
+}
+
+// Decompress the input layer by layer, such as `.gz` of `.bz2`, up to `options.decompress_depth` layers.
+fn unwrap_layers<'a>(reader: Box<dyn Read + 'a>, options: &InvokeOptions) -> Result<Unwrapped<'a>> {
+    let guard = Guard::new(options);
+    let mut layers = Vec::new();
+    let (mut header, mut reader) = peek(Box::new(guard.count_compressed(reader)))?;
+
+    while let Some(compressed_format) = outermost_layer(&header) {
+        if layers.len() >= options.decompress_depth {
+            warn!(
+                "The input is compressed in more than {} layers. Parsing the rest as is.",
+                options.decompress_depth
+            );
+            break;
+        }
+        debug!(
+            "Decompressing the layer {} in {:?} format",
+            layers.len() + 1,
+            compressed_format
+        );
+        (header, reader) = peek(compressed_format.decoder(reader)?)?;
+        layers.push(compressed_format);
+    }
+
+    // the content read through all the layers, including the header peeked above, is checked against the limits.
+    let reader: Box<dyn Read + 'a> = if layers.is_empty() {
+        Box::new(DeadlineReader::new(reader, options.deadline))
+    } else {
+        Box::new(guard.check_decompressed(reader))
+    };
+
+    Ok(Unwrapped {
+        reader,
+        layers,
+        header,
+    })
+}
+
+// check if the content is binary, such as BAM in BGZF or CRAM, by looking for NUL bytes in the first bytes, which text formats do not contain.
+// binary contents are sampled as bytes instead of lines.
+fn is_binary(header: &[u8]) -> bool {
+    header.contains(&0)
+}
+
+// infer the compression format from the first bytes of the input.
+pub fn infer_compressed_format(buffer: &[u8]) -> CompressedFormat {
+    let Some(inferred_type) = infer::get(buffer) else {
+        // `infer` does not know the legacy LZMA format, which has no magic bytes.
+        if is_lzma_alone(buffer) {
+            debug!("Provided input is in LZMA format");
+            return CompressedFormat::Lzma;
+        }
+        // type was not inferred, return None
+        debug!("Compressed format of the input is not inferrable, or the input is not compressed. Parsing the input as is.");
+        return CompressedFormat::None;
+    };
+
+    let compressed_format = match inferred_type.extension() {
+        // check if the gz file is in BGZF format
+        "gz" if is_gzfile_in_bgzf(buffer) => classify_bgzf(buffer),
+        "gz" => CompressedFormat::GZ,
+        "bz2" => CompressedFormat::BZ2,
+        "zst" => CompressedFormat::Zstd,
+        "xz" => CompressedFormat::Xz,
+        "lz4" => CompressedFormat::Lz4,
+        _ => {
+            warn!("Provided input is in compressed format not supported by this tool. Parsing the input as is.");
+            return CompressedFormat::None;
+        }
+    };
+    debug!("Provided input is in {:?} format", compressed_format);
+    compressed_format
+}
+
+// BAM and BCF in BGZF are passed to their readers as is, and the other formats in BGZF, such as bgzipped VCF, are decompressed.
+// The header must hold the whole first block, as read by `read_header`.
+fn classify_bgzf(header_buffer: &[u8]) -> CompressedFormat {
+    let mut magic = [0; 4];
+    match noodles::bgzf::Reader::new(header_buffer).read_exact(&mut magic) {
+        Ok(()) if magic == *b"BAM\x01" || magic[..3] == *b"BCF" => CompressedFormat::Bgzf,
+        Ok(()) => CompressedFormat::BgzfText,
+        // the first block is corrupted or cut off, or holds less than the magic. It is decompressed like the other formats, which reports a broken block as an error.
+        Err(e) => {
+            debug!("Failed to inflate the first BGZF block: {}", e);
+            CompressedFormat::BgzfText
+        }
+    }
+}
+
+// check if the input is in the legacy LZMA format (.lzma), written by `xz --format=lzma` or `lzma`.
+// The header starts with the properties byte, which is 0x5D with the default settings, followed by the dictionary size in little endian.
+fn is_lzma_alone(header_buffer: &[u8]) -> bool {
+    header_buffer.len() >= 13
+        && header_buffer[0] == 0x5D
+        && u32::from_le_bytes([
+            header_buffer[1],
+            header_buffer[2],
+            header_buffer[3],
+            header_buffer[4],
+        ])
+        .is_power_of_two()
+}
+
 // check if the gz file is particulary in BGZF format
 const fn is_gzfile_in_bgzf(header_buffer: &[u8]) -> bool {
     // check if the header is BGZF
 
     // check if the header is long enough
-    let header_buffer_length = header_buffer.len() >= 15;
+    if header_buffer.len() < 15 {
+        return false;
+    }
 
     // GZ Flag = 4, means that there is an extra field
     let flag = header_buffer[3] == 0x04;
@@ -321,13 +413,54 @@ const fn is_gzfile_in_bgzf(header_buffer: &[u8]) -> bool {
     // subfield length in the extra field is 2
     let slen = header_buffer[14] == 0x02;
 
-    header_buffer_length && flag && si1 && si2 && slen
+    flag && si1 && si2 && slen
 }
 
 #[derive(Debug)]
 pub enum CompressedFormat {
+    // BGZF whose content is parsed as is by the BAM and BCF parsers
     Bgzf,
+    // BGZF whose content is decompressed, such as bgzipped VCF, BED or FASTA
+    BgzfText,
     GZ,
     BZ2,
+    Zstd,
+    Xz,
+    Lzma,
+    Lz4,
     None,
 }
+
+impl CompressedFormat {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Bgzf | Self::BgzfText => "bgzf",
+            Self::GZ => "gzip",
+            Self::BZ2 => "bzip2",
+            Self::Zstd => "zstd",
+            Self::Xz => "xz",
+            Self::Lzma => "lzma",
+            Self::Lz4 => "lz4",
+            Self::None => "none",
+        }
+    }
+
+    // wrap the reader with the decoder of the format. Plain inputs and BAM/BCF in BGZF are returned as is, as they are parsed without being decompressed.
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        let decoder: Box<dyn Read + 'a> = match self {
+            Self::GZ => Box::new(GzDecoder::new(reader)),
+            Self::BgzfText => Box::new(noodles::bgzf::Reader::new(reader)),
+            Self::BZ2 => Box::new(BzDecoder::new(reader)),
+            Self::Zstd => Box::new(zstd::Decoder::new(reader)?),
+            // concatenated streams are decompressed as one, as `xz` does.
+            Self::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Self::Lzma => Box::new(XzDecoder::new_stream(
+                reader,
+                Stream::new_lzma_decoder(u64::MAX)?,
+            )),
+            Self::Lz4 => Box::new(FrameDecoder::new(reader)),
+            Self::Bgzf | Self::None => Box::new(reader),
+        };
+        Ok(decoder)
+    }
+}
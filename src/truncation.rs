@@ -0,0 +1,103 @@
+use anyhow::Result;
+use flate2::read::DeflateDecoder;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The empty block which ends every BGZF file, such as BAM, BCF and bgzipped VCF, as given in the SAM/BAM specification.
+const EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Maximum size of a BGZF block.
+pub const MAX_BLOCK_SIZE: usize = 65536;
+
+/// Number of bytes at the end of a BGZF file to check, which hold the last block followed by the EOF marker.
+pub const TAIL_LENGTH: u64 = (MAX_BLOCK_SIZE + EOF_MARKER.len()) as u64;
+
+const GZIP_MAGIC: [u8; 4] = [0x1f, 0x8b, 0x08, 0x04];
+
+// Whether the input starting with the header is compressed in BGZF, whose end is checked for truncation.
+pub fn is_bgzf(header: &[u8]) -> bool {
+    header.starts_with(&GZIP_MAGIC) && block_size(header).is_some()
+}
+
+// Check the end of the local file if it is compressed in BGZF, which is read by seeking to the end rather than reading the whole file.
+pub fn check_file(path: &Path) -> Result<Option<String>> {
+    let mut file = File::open(path)?;
+    // the gzip header up to the `BC` subfield of the first block
+    let mut header = Vec::new();
+    (&mut file).take(18).read_to_end(&mut header)?;
+    if !is_bgzf(&header) {
+        return Ok(None);
+    }
+
+    let length = file.metadata()?.len();
+    file.seek(SeekFrom::Start(length.saturating_sub(TAIL_LENGTH)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    Ok(check_tail(&tail))
+}
+
+// Check the last `TAIL_LENGTH` bytes of a BGZF file, or the whole file if it is shorter, and return why the file is truncated if it is.
+// The file must end with the EOF marker, preceded by an intact block whose data is inflated into the size and CRC32 in its footer.
+pub fn check_tail(tail: &[u8]) -> Option<String> {
+    match tail.strip_suffix(&EOF_MARKER) {
+        Some(body) if body.is_empty() || last_block(body).is_some() => None,
+        Some(_) => Some("The last BGZF block before the EOF marker is incomplete or corrupted".to_string()),
+        None if last_block(tail).is_some() => Some(
+            "The BGZF EOF marker is missing, so the file may have been cut off at the end of a block"
+                .to_string(),
+        ),
+        None => Some(
+            "The file ends in the middle of a BGZF block, without the BGZF EOF marker".to_string(),
+        ),
+    }
+}
+
+// The intact block ending at the end of `data`.
+fn last_block(data: &[u8]) -> Option<&[u8]> {
+    let start = data.len().saturating_sub(MAX_BLOCK_SIZE);
+    (start..data.len())
+        .filter(|&offset| data[offset..].starts_with(&GZIP_MAGIC))
+        .map(|offset| &data[offset..])
+        .find(|block| block_size(block) == Some(block.len()) && is_intact(block))
+}
+
+// The size of the block starting at the beginning of `data`, given by the `BC` subfield of its gzip header.
+pub fn block_size(data: &[u8]) -> Option<usize> {
+    let extra_length = usize::from(u16::from_le_bytes([*data.get(10)?, *data.get(11)?]));
+    let mut subfields = data.get(12..12 + extra_length)?;
+    while subfields.len() >= 4 {
+        let length = usize::from(u16::from_le_bytes([subfields[2], subfields[3]]));
+        let value = subfields.get(4..4 + length)?;
+        if subfields[..2] == *b"BC" && length == 2 {
+            return Some(usize::from(u16::from_le_bytes([value[0], value[1]])) + 1);
+        }
+        subfields = &subfields[4 + length..];
+    }
+    None
+}
+
+// Whether the deflated data of the block is inflated into the size and the CRC32 given in its footer.
+fn is_intact(block: &[u8]) -> bool {
+    let extra_length = usize::from(u16::from_le_bytes([block[10], block[11]]));
+    let Some(deflated) = block.get(12 + extra_length..block.len().saturating_sub(8)) else {
+        return false;
+    };
+    let footer = &block[block.len() - 8..];
+    let crc32 = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]);
+    let size = u32::from_le_bytes([footer[4], footer[5], footer[6], footer[7]]);
+
+    let mut inflated = Vec::new();
+    if DeflateDecoder::new(deflated)
+        .read_to_end(&mut inflated)
+        .is_err()
+    {
+        return false;
+    }
+    let mut crc = flate2::Crc::new();
+    crc.update(&inflated);
+    inflated.len() == size as usize && crc.sum() == crc32
+}
@@ -1,8 +1,11 @@
 // use sha2::Sha256;
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use assert_cmd::cargo;
 
@@ -23,6 +26,56 @@ pub fn tataki(targets: &[&str], options: &[&str]) -> Out {
     }
 }
 
+// run tataki expecting it to exit with the given code.
+pub fn tataki_with_code(targets: &[&str], options: &[&str], code: i32) -> Out {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    cmd.current_dir("tests/");
+    let assert = cmd.args(targets).args(options).assert().code(code);
+
+    Out {
+        stdout: String::from_utf8_lossy(&assert.get_output().stdout).to_string(),
+        stderr: String::from_utf8_lossy(&assert.get_output().stderr).to_string(),
+    }
+}
+
+// run tataki with the environment variables, such as the credentials of the remote services.
+#[allow(dead_code)]
+pub fn tataki_with_env(targets: &[&str], options: &[&str], envs: &[(&str, &str)]) -> Out {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    cmd.current_dir("tests/");
+    let assert = cmd
+        .args(targets)
+        .args(options)
+        .envs(envs.iter().copied())
+        .assert()
+        .success();
+
+    Out {
+        stdout: String::from_utf8_lossy(&assert.get_output().stdout).to_string(),
+        stderr: String::from_utf8_lossy(&assert.get_output().stderr).to_string(),
+    }
+}
+
+// run tataki with the given bytes as STDIN.
+pub fn tataki_with_stdin(targets: &[&str], options: &[&str], stdin: Vec<u8>) -> Out {
+    let mut cmd = cargo::cargo_bin_cmd!();
+
+    cmd.current_dir("tests/");
+    let assert = cmd
+        .args(targets)
+        .args(options)
+        .write_stdin(stdin)
+        .assert()
+        .success();
+
+    Out {
+        stdout: String::from_utf8_lossy(&assert.get_output().stdout).to_string(),
+        stderr: String::from_utf8_lossy(&assert.get_output().stderr).to_string(),
+    }
+}
+
 pub fn calculate_checksum<P>(path: P) -> io::Result<String>
 where
     P: AsRef<Path>,
@@ -49,6 +102,19 @@ pub fn check_and_create_cache_dir() -> io::Result<()> {
     Ok(())
 }
 
+// the temporary directory kept in the cache directory, as told by the log with `-v`. The path is relative to `tests/`.
+#[allow(dead_code)]
+pub fn kept_dir(stderr: &str) -> PathBuf {
+    Path::new("tests").join(
+        stderr
+            .split("Keeping temporary directory:")
+            .nth(1)
+            .and_then(|rest| rest.lines().next())
+            .expect("The temporary directory is not kept")
+            .trim(),
+    )
+}
+
 // whether the current environment is M1 Mac
 #[allow(dead_code)]
 pub fn is_running_on_m1_mac() -> bool {
@@ -56,3 +122,291 @@ pub fn is_running_on_m1_mac() -> bool {
     let arch = std::env::consts::ARCH;
     os == "macos" && arch == "aarch64"
 }
+
+// serve `body` at http://127.0.0.1:<port>/<file_name> from a background thread for the lifetime of the test process.
+// Without `with_content_length`, the size is not told to the client and the body ends by closing the connection.
+#[allow(dead_code)]
+pub fn serve_http(file_name: &str, body: Vec<u8>, with_content_length: bool) -> String {
+    let server = TestServer::start(with_content_length);
+    server.route(&format!("/{}", file_name), body);
+    format!("{}/{}", server.base_url, file_name)
+}
+
+// A local HTTP server standing in for the remote services, which serves the routed bodies to GET and POST requests and records the requests it has received.
+// The range of the body given by `Range: bytes=<start>-`, or the last bytes given by `Range: bytes=-<length>`, is served with 206 Partial Content, and the ETag set for the path is sent along with the body.
+#[allow(dead_code)]
+pub struct TestServer {
+    /// `http://127.0.0.1:<port>` without the trailing slash
+    pub base_url: String,
+    routes: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+    failures: Arc<Mutex<HashMap<String, usize>>>,
+    etags: Arc<Mutex<HashMap<String, String>>>,
+}
+
+#[allow(dead_code)]
+impl TestServer {
+    pub fn start(with_content_length: bool) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind a local port");
+        let base_url = format!(
+            "http://{}",
+            listener
+                .local_addr()
+                .expect("Failed to get the local address")
+        );
+        let routes: Arc<Mutex<HashMap<String, Vec<u8>>>> = Arc::default();
+        let requests: Arc<Mutex<Vec<String>>> = Arc::default();
+        let failures: Arc<Mutex<HashMap<String, usize>>> = Arc::default();
+        let etags: Arc<Mutex<HashMap<String, String>>> = Arc::default();
+
+        let (thread_routes, thread_requests, thread_failures, thread_etags) = (
+            routes.clone(),
+            requests.clone(),
+            failures.clone(),
+            etags.clone(),
+        );
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                // read the request up to the empty line ending the headers, and the body if any.
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                // the headers are in lowercase to be found regardless of how the client writes them.
+                let mut request = request_line.clone();
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|size| size > 2) {
+                    request.push_str(&line.to_lowercase());
+                    line.clear();
+                }
+                let content_length = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .and_then(|length| length.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; content_length];
+                if reader.read_exact(&mut body).is_ok() {
+                    request.push('\n');
+                    request.push_str(&String::from_utf8_lossy(&body));
+                }
+
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let range = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .map(|range| range.trim().to_string());
+                thread_requests.lock().unwrap().push(request);
+
+                let failing = thread_failures
+                    .lock()
+                    .unwrap()
+                    .get_mut(&path)
+                    .filter(|times| **times > 0)
+                    .map(|times| *times -= 1)
+                    .is_some();
+                let routed = thread_routes.lock().unwrap().get(&path).cloned();
+                let range_start = routed
+                    .as_ref()
+                    .zip(range)
+                    .and_then(|(body, range)| match range.strip_prefix('-') {
+                        Some(length) => Some(body.len().saturating_sub(length.parse().ok()?)),
+                        None => range.trim_end_matches('-').parse::<usize>().ok(),
+                    });
+                let (status, body) = match (routed, range_start) {
+                    _ if failing => ("503 Service Unavailable", Vec::new()),
+                    (Some(body), Some(start)) if start < body.len() => {
+                        ("206 Partial Content", body[start..].to_vec())
+                    }
+                    (Some(body), _) => ("200 OK", body),
+                    (None, _) => ("404 Not Found", Vec::new()),
+                };
+                let mut header = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+                if with_content_length {
+                    header.push_str(&format!("Content-Length: {}\r\n", body.len()));
+                }
+                if let Some(etag) = thread_etags.lock().unwrap().get(&path) {
+                    header.push_str(&format!("ETag: \"{}\"\r\n", etag));
+                }
+                header.push_str("\r\n");
+                // the client may stop reading in the middle of the body.
+                let _ = stream
+                    .write_all(header.as_bytes())
+                    .and_then(|()| stream.write_all(&body));
+            }
+        });
+
+        Self {
+            base_url,
+            routes,
+            requests,
+            failures,
+            etags,
+        }
+    }
+
+    // serve `body` at the path, which includes the query if any, such as "/reads/toy?format=BAM".
+    pub fn route(&self, path: &str, body: Vec<u8>) {
+        self.routes.lock().unwrap().insert(path.to_string(), body);
+    }
+
+    // the requests received so far, each of which is the request line and the headers in lowercase, followed by the body if any.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // send the ETag with the body served at the path, which tells whether the content has changed.
+    pub fn set_etag(&self, path: &str, etag: &str) {
+        self.etags
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), etag.to_string());
+    }
+
+    // respond to the next `times` requests to the path with 503 Service Unavailable.
+    pub fn fail(&self, path: &str, times: usize) {
+        self.failures
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), times);
+    }
+}
+
+// A local FTP server standing in for the public FTP servers, which serves the files to anonymous users in the passive mode and records the commands it has received.
+// The first `aborted_transfers` transfers are aborted in the middle of the file.
+#[allow(dead_code)]
+pub struct FtpServer {
+    /// `ftp://127.0.0.1:<port>` without the trailing slash
+    pub base_url: String,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+#[allow(dead_code)]
+impl FtpServer {
+    pub fn start(files: HashMap<String, Vec<u8>>, aborted_transfers: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind a local port");
+        let base_url = format!(
+            "ftp://{}",
+            listener
+                .local_addr()
+                .expect("Failed to get the local address")
+        );
+        let commands: Arc<Mutex<Vec<String>>> = Arc::default();
+        let files = Arc::new(files);
+        let aborted_transfers = Arc::new(Mutex::new(aborted_transfers));
+
+        let thread_commands = commands.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let (files, commands, aborted_transfers) = (
+                    files.clone(),
+                    thread_commands.clone(),
+                    aborted_transfers.clone(),
+                );
+                std::thread::spawn(move || {
+                    serve_ftp_session(stream, &files, &commands, &aborted_transfers)
+                });
+            }
+        });
+
+        Self { base_url, commands }
+    }
+
+    // the commands received so far, such as "RETR pub/toy.fq".
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+}
+
+fn serve_ftp_session(
+    stream: std::net::TcpStream,
+    files: &HashMap<String, Vec<u8>>,
+    commands: &Mutex<Vec<String>>,
+    aborted_transfers: &Mutex<usize>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(b"220 Test FTP server\r\n")?;
+
+    let mut data_listener: Option<TcpListener> = None;
+    let mut offset = 0;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let command = line.trim_end().to_string();
+        line.clear();
+        commands.lock().unwrap().push(command.clone());
+        let (name, argument) = command.split_once(' ').unwrap_or((&command, ""));
+
+        let reply = match (name, files.get(argument)) {
+            ("USER", _) => "331 Password required".to_string(),
+            ("PASS", _) => "230 Logged in".to_string(),
+            ("TYPE", _) => "200 Type set".to_string(),
+            ("SIZE", Some(file)) => format!("213 {}", file.len()),
+            ("MDTM", Some(_)) => "213 20240101000000".to_string(),
+            ("REST", _) => {
+                offset = argument.parse().unwrap_or(0);
+                format!("350 Restarting at {}", offset)
+            }
+            ("EPSV" | "PASV", _) => {
+                let listener = TcpListener::bind("127.0.0.1:0")?;
+                let port = listener.local_addr()?.port();
+                data_listener = Some(listener);
+                if name == "EPSV" {
+                    format!("229 Entering Extended Passive Mode (|||{}|)", port)
+                } else {
+                    format!(
+                        "227 Entering Passive Mode (127,0,0,1,{},{})",
+                        port / 256,
+                        port % 256
+                    )
+                }
+            }
+            ("RETR", Some(file)) => {
+                writer.write_all(b"150 Opening data connection\r\n")?;
+                let (mut data, _) = data_listener
+                    .take()
+                    .expect("RETR without the passive mode")
+                    .accept()?;
+                let start = offset.min(file.len());
+                offset = 0;
+                let aborted = {
+                    let mut aborted_transfers = aborted_transfers.lock().unwrap();
+                    let aborted = *aborted_transfers > 0;
+                    *aborted_transfers = aborted_transfers.saturating_sub(1);
+                    aborted
+                };
+                let end = if aborted {
+                    start + (file.len() - start) / 2
+                } else {
+                    file.len()
+                };
+                // the client may stop reading in the middle of the file.
+                let _ = data.write_all(&file[start..end]);
+                drop(data);
+                if aborted {
+                    "426 Connection closed; transfer aborted".to_string()
+                } else {
+                    "226 Transfer complete".to_string()
+                }
+            }
+            ("SIZE" | "MDTM" | "RETR", None) => "550 No such file".to_string(),
+            ("QUIT", _) => {
+                writer.write_all(b"221 Bye\r\n")?;
+                return Ok(());
+            }
+            _ => "502 Command not implemented".to_string(),
+        };
+        writer.write_all(format!("{}\r\n", reply).as_bytes())?;
+    }
+    Ok(())
+}